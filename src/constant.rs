@@ -37,14 +37,4 @@ pub const Q: u16 = 3329;
 
 pub const N: usize = 256;
 
-pub const G: u16 = 17;
-
-pub const K: usize = 4;
-
-pub const ETA1: usize = 2;
-
-pub const ETA2: usize = 2;
-
-pub const DU : usize = 11;
-
-pub const DV: usize = 5;
\ No newline at end of file
+pub const G: u16 = 17;
\ No newline at end of file
@@ -0,0 +1,19 @@
+use core::fmt;
+
+// Secret-bearing types implement a redacted Debug. Their contents can only be printed by
+// explicitly opting in, e.g. println!("{:?}", dk.unredacted()).
+pub trait DebugSecret {
+    fn fmt_secret(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result;
+
+    fn unredacted(&self) -> Unredacted<'_, Self> {
+        Unredacted(self)
+    }
+}
+
+pub struct Unredacted<'a, T: ?Sized>(&'a T);
+
+impl<T: DebugSecret + ?Sized> fmt::Debug for Unredacted<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt_secret(f)
+    }
+}
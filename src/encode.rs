@@ -1,69 +1,57 @@
-use std::vec;
-use crate::constant::{Q, N};
+use crate::constant::N;
+use crate::field::FieldElement as FF;
+use crate::error::Error;
 
 // Algorithm 3: Converts a bit array (of a length that is a multiple of eight) into an array of bytes in little endian order.
-pub fn bits_to_bytes(mut bits: Vec<u16>) -> Vec<u16> {
-    while bits.len() % 8 != 0 {
-        bits.push(0);
+pub fn bits_to_bytes(bits: &[u8], bytes: &mut [u8]) {
+    bytes.fill(0);
+    for i in 0..bits.len() {
+        bytes[i / 8] |= bits[i] << (i % 8);
     }
-    let l = bits.len();
-    let mut bytes: Vec<u16> = vec![0; l / 8];
-    for i in 0..l {
-        bytes[i / 8] = bytes[i / 8] + (bits[i] << (i % 8)); 
-    }
-    bytes
 }
 
 // Algorithm 4: Converts an array of bytes in little endian into a bit array
-pub fn bytes_to_bits(mut bytes: Vec<u16>) -> Vec<u16> {
-    let l = bytes.len();
-    let mut bits = vec![0; l * 8];
-    for i in 0..l {
+pub fn bytes_to_bits(bytes: &[u8], bits: &mut [u8]) {
+    for i in 0..bytes.len() {
         for j in 0..8 {
-            bits[i * 8 + j] = bytes[i] & 1;
-            bytes[i] = bytes[i] >> 1;
+            bits[i * 8 + j] = (bytes[i] >> j) & 1;
         }
     }
-    bits
 }
 
 //Algorithm 5: Encodes an array of 𝑑-bit integers into a byte array for 1 ≤ 𝑑 ≤ 12.
-pub fn bytes_encode(d: usize, mut f: Vec<u16>) -> Vec<u16> {
-    while f.len() != N {
-        f.push(0);
+// The 32d output bytes are written to b, one bit at a time without a bit array.
+pub fn bytes_encode(d: usize, f: &[u16; N], b: &mut [u8]) -> Result<(), Error> {
+    if b.len() != 32 * d {
+        return Err(Error::InvalidLength);
     }
-    let mut b = vec![0u16; N * d];
-    for i in 0..N {
-        let mut a = f[i];
+    b.fill(0);
+    for (i, x) in f.iter().enumerate() {
         for j in 0..d {
-            b[i * d + j] = a & 1;
-            a = a >> 1;
+            let k = i * d + j;
+            b[k / 8] |= (((x >> j) & 1) as u8) << (k % 8);
         }
     }
-    bits_to_bytes(b)
+    Ok(())
 }
 
 //Algorithm 6: Decodes a byte array into an array of 𝑑-bit integers for 1 ≤ 𝑑 ≤ 12.
-pub fn bytes_decode(d: usize, mut bytes: Vec<u16>) -> Vec<u16> {
-    let mut m = 1 << d;
-    if d == 12 {
-        m = Q;
-    }
-
-    while bytes.len() != 32 * d {
-        bytes.push(0);
+pub fn bytes_decode(d: usize, bytes: &[u8], f: &mut [u16; N]) -> Result<(), Error> {
+    if bytes.len() != 32 * d {
+        return Err(Error::InvalidLength);
     }
-
-    let bit = bytes_to_bits(bytes);
-    assert_eq!(bit.len(), N * d);
-    let mut f = vec![0u16; N];
-    for i in 0..N {
+    for (i, x) in f.iter_mut().enumerate() {
+        *x = 0;
         for j in 0..d {
-            f[i] += bit[i * d + j] << j;
+            let k = i * d + j;
+            *x |= (((bytes[k / 8] >> (k % 8)) & 1) as u16) << j;
+        }
+        // Coefficients modulo 2^d need no reduction; for d = 12 reduce modulo Q without a division
+        if d == 12 {
+            *x = FF::new(*x).to_int();
         }
-        f[i] %= m;
     }
-    f
+    Ok(())
 }
 
 #[cfg(test)]
@@ -72,25 +60,41 @@ mod tests {
 
     #[test]
     fn test_bits_to_bytes() {
-        let vec = vec![1, 0, 1, 0, 1, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 1];
-        let bytes = bits_to_bytes(vec);
-        assert_eq!(bytes, vec![85, 128]);
+        let bits = [1, 0, 1, 0, 1, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+        let mut bytes = [0xff; 2];
+        bits_to_bytes(&bits, &mut bytes);
+        assert_eq!(bytes, [85, 128]);
     }
 
     #[test]
     fn test_bytes_to_bits() {
-        let vec = vec![85, 128];
-        let bits = bytes_to_bits(vec);
-        assert_eq!(bits, vec![1, 0, 1, 0, 1, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        let mut bits = [0; 16];
+        bytes_to_bits(&[85, 128], &mut bits);
+        assert_eq!(bits, [1, 0, 1, 0, 1, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
     }
 
     #[test]
     fn test_bytes_encode_decode() {
         let d = 5;
-        let mut f = vec![0b11110, 0b10100, 0b11000, 0b10010, 0b11101];
-        f.append(&mut vec![0; N - f.len()]);
-        let bytes = bytes_encode(d, f.clone());
-        let f_ = bytes_decode(d, bytes);
+        let mut f = [0u16; N];
+        f[..5].copy_from_slice(&[0b11110, 0b10100, 0b11000, 0b10010, 0b11101]);
+        let mut bytes = [0xff; 32 * 5];
+        bytes_encode(d, &f, &mut bytes).unwrap();
+        let mut f_ = [0xffff; N];
+        bytes_decode(d, &bytes, &mut f_).unwrap();
         assert_eq!(f, f_);
     }
+
+    #[test]
+    fn test_bytes_decode_length() {
+        assert_eq!(bytes_decode(1, &[0; 33], &mut [0; N]), Err(Error::InvalidLength));
+        assert_eq!(bytes_decode(1, &[0; 31], &mut [0; N]), Err(Error::InvalidLength));
+        assert_eq!(bytes_decode(12, &[], &mut [0; N]), Err(Error::InvalidLength));
+    }
+
+    #[test]
+    fn test_bytes_encode_length() {
+        assert_eq!(bytes_encode(1, &[0; N], &mut [0; 31]), Err(Error::InvalidLength));
+        assert_eq!(bytes_encode(1, &[0; N], &mut [0; 33]), Err(Error::InvalidLength));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,39 @@
+use core::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    // Input does not have the length required by the parameter set
+    InvalidLength,
+    // Encapsulation key failed the modulus check (Section 7.2)
+    ModulusCheck,
+    // Decapsulation key failed the hash check (Section 7.3)
+    HashCheck,
+    // Random bit generator failed to produce output
+    Rng,
+    // Seed and expanded decapsulation key do not match
+    KeyMismatch,
+    // Generated key pair failed the pairwise consistency test
+    PairwiseConsistency,
+    // Power-on self-tests failed, the module refuses to operate
+    SelfTestFailed,
+    // Locked memory for secret material could not be allocated
+    LockedMemory,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidLength => write!(f, "invalid input length"),
+            Error::ModulusCheck => write!(f, "encapsulation key failed the modulus check"),
+            Error::HashCheck => write!(f, "decapsulation key failed the hash check"),
+            Error::Rng => write!(f, "random bit generation failed"),
+            Error::KeyMismatch => write!(f, "seed does not match the decapsulation key"),
+            Error::PairwiseConsistency => write!(f, "key pair failed the pairwise consistency test"),
+            Error::SelfTestFailed => write!(f, "self-tests failed"),
+            Error::LockedMemory => write!(f, "locked memory allocation failed"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
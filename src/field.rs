@@ -1,14 +1,36 @@
-use std::ops::{Add, Sub, Mul};
-use std::cmp::{PartialEq, Eq};
+use core::ops::{Add, Sub, Mul};
+use core::cmp::{PartialEq, Eq};
 pub type Integer = u16;
 use crate::constant::Q;
+use zeroize::Zeroize;
+use core::fmt;
+use crate::debug::DebugSecret;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Clone, Copy)]
 pub struct FieldElement(pub Integer);
 
+// Branch-free reductions, so that secret-dependent arithmetic does not use a hardware division.
+// Subtracts Q from a in [0, 2Q) if a >= Q
+fn csub(a: Integer) -> Integer {
+    let t = a.wrapping_sub(Q);
+    t.wrapping_add(((t as i16) >> 15) as Integer & Q)
+}
+
+// Barrett reduction of a 16-bit value, with floor(2^24 / Q) = 5039
+fn barrett_reduce(a: Integer) -> Integer {
+    let t = ((a as u32 * 5039) >> 24) as Integer;
+    csub(a - t * Q)
+}
+
+// Barrett reduction of a product in [0, Q^2), with floor(2^32 / Q) = 1290167
+fn barrett_reduce_product(a: u32) -> Integer {
+    let t = ((a as u64 * 1290167) >> 32) as u32;
+    csub((a - t * Q as u32) as Integer)
+}
+
 impl FieldElement {
     pub fn new(value: Integer) -> FieldElement {
-        FieldElement(value % Q)
+        FieldElement(barrett_reduce(value))
     }
 
     pub fn pow(&self, exponent: Integer) -> FieldElement {
@@ -38,7 +60,7 @@ impl Add<FieldElement> for FieldElement {
     type Output = FieldElement;
 
     fn add(self, other: FieldElement) -> FieldElement {
-        FieldElement((self.0 + other.0) % Q)
+        FieldElement(csub(self.0 + other.0))
     }
 }
 
@@ -46,7 +68,7 @@ impl Sub<FieldElement> for FieldElement {
     type Output = FieldElement;
 
     fn sub(self, other: FieldElement) -> FieldElement {
-        FieldElement((self.0 + Q - other.0) % Q)
+        FieldElement(csub(self.0 + Q - other.0))
     }
 }
 
@@ -54,9 +76,7 @@ impl Mul<FieldElement> for FieldElement {
     type Output = FieldElement;
 
     fn mul(self, other: FieldElement) -> FieldElement {
-        let _q = Q as u32;
-        let _ans = ((self.0 as u32) * (other.0 as u32)) % _q;
-        FieldElement(_ans as Integer)
+        FieldElement(barrett_reduce_product((self.0 as u32) * (other.0 as u32)))
     }
 }
 
@@ -68,10 +88,29 @@ impl PartialEq for FieldElement {
 
 impl Eq for FieldElement {}
 
+impl fmt::Debug for FieldElement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("FieldElement(..)")
+    }
+}
+
+impl DebugSecret for FieldElement {
+    fn fmt_secret(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("FieldElement").field(&self.0).finish()
+    }
+}
+
+impl Zeroize for FieldElement {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::format;
 
     #[test]
     fn test_add() {
@@ -97,6 +136,32 @@ mod tests {
         assert_eq!(a * b, c);
     }
 
+    #[test]
+    fn test_new_exhaustive() {
+        for a in 0..=Integer::MAX {
+            assert_eq!(FieldElement::new(a).0, a % Q);
+        }
+    }
+
+    #[test]
+    fn test_arithmetic_exhaustive() {
+        for a in 0..Q {
+            for b in 0..Q {
+                let (x, y) = (FieldElement(a), FieldElement(b));
+                assert_eq!((x + y).0, (a + b) % Q);
+                assert_eq!((x - y).0, (a + Q - b) % Q);
+                assert_eq!((x * y).0, ((a as u32 * b as u32) % Q as u32) as Integer);
+            }
+        }
+    }
+
+    #[test]
+    fn test_debug_redacted() {
+        let a = FieldElement(1234);
+        assert_eq!(format!("{:?}", a), "FieldElement(..)");
+        assert_eq!(format!("{:?}", a.unredacted()), "FieldElement(1234)");
+    }
+
     #[test]
     fn test_pow() {
         let a = FieldElement(3);
@@ -1,29 +1,32 @@
 use crate::field::FieldElement as FF;
-use sha3::{Shake128, Shake256, Sha3_256, Sha3_512, Digest, digest::{Update, ExtendableOutput, XofReader}};
-use rand::Rng;
+use sha3::{Shake128, Shake128Reader, Shake256, Sha3_256, Sha3_512, Digest, digest::{Update, ExtendableOutput, XofReader}};
+use rand::{CryptoRng, RngCore};
+use crate::error::Error;
+use zeroize::Zeroize;
 
 // Compress/Decompress function
-fn field_reduce(x: u16) -> u16 {
-    let wx = x.wrapping_sub(3329);
-    wx.wrapping_add((wx >> 15).wrapping_mul(3329))
+// No runtime division is used on (possibly secret) coefficients, see KyberSlash.
+// round(2^d / q * x) mod 2^d for x in [0, q), dividing by q as a multiplication by ceil(2^35 / q) and a shift
+fn compress_coeff(x: u16, d: u8) -> u16 {
+    let dividend = ((x as u64) << d) + 1664;
+    (((dividend * 10321340) >> 35) as u16) & ((1 << d) - 1)
 }
 
-fn div_and_round(dividend: u32, divisor: u32) -> u16 {
-    field_reduce(((dividend + (divisor >> 1)) / divisor) as u16)
+// round(q / 2^d * y), where the division by 2^d is a shift
+fn decompress_coeff(y: u16, d: u8) -> u16 {
+    (((y as u32) * 3329 + (1 << (d - 1))) >> d) as u16
 }
 
-pub fn compress(mut v: Vec<u16>, d: u8) -> Vec<u16> {
-    for i in 0..v.len() {
-        v[i] = div_and_round((v[i] as u32) << d, 3329);
+pub fn compress(v: &mut [u16], d: u8) {
+    for x in v.iter_mut() {
+        *x = compress_coeff(*x, d);
     }
-    v
 }
 
-pub fn decompress(mut v: Vec<u16>, d: u8) -> Vec<u16> {
-    for i in 0..v.len() {
-        v[i] = div_and_round((v[i] as u32) * 3329, 1u32 << d);
+pub fn decompress(v: &mut [u16], d: u8) {
+    for x in v.iter_mut() {
+        *x = decompress_coeff(*x, d);
     }
-    v
 }
 
 // Algorithm 12: Computes the product of two degree-one polynomials with respect to a quadratic modulus.
@@ -33,50 +36,146 @@ pub fn base_case_multiply(a0: FF, a1: FF, b0: FF, b1: FF, gamma: FF) -> (FF, FF)
     (c0, c1)
 }
 
-// XOF function
-pub fn xof(input: Vec<u8>) -> Vec<u16> {
-    let mut xof = Shake128::default();
-    xof.update(&input);
-    let mut output = vec![0u8; 3];
-    xof.finalize_xof().read(&mut output);
-    output.iter().map(|x| *x as u16).collect()
+// XOF function, incremental SHAKE128 with XOF.Init, XOF.Absorb and XOF.Squeeze (Section 4.1)
+pub struct Xof {
+    reader: Shake128Reader,
 }
 
-// PRF function
-pub fn prf(eta: usize, mut s: Vec<u8>, b: u8) -> Vec<u16> {
-    let size = 64 * eta;
-    s.push(b);
+impl Xof {
+    pub fn new(input: &[u8]) -> Xof {
+        let mut xof = Shake128::default();
+        xof.update(input);
+        Xof { reader: xof.finalize_xof() }
+    }
+
+    pub fn squeeze(&mut self, output: &mut [u8]) {
+        self.reader.read(output);
+    }
+}
+
+// PRF function, PRF_eta(s, b) with the 64 * eta output bytes written to output
+pub fn prf(s: &[u8], b: u8, output: &mut [u8]) {
     let mut shake256 = Shake256::default();
-    shake256.update(&s);
-    let mut output = vec![0u8; size];
-    shake256.finalize_xof().read(&mut output);
-    output.iter().map(|x| *x as u16).collect()
+    shake256.update(s);
+    shake256.update(&[b]);
+    shake256.finalize_xof().read(output);
 }
 
 // Some hash functions
-pub fn h(s: Vec<u8>) -> Vec<u8> {
+pub fn h(s: &[u8]) -> [u8; 32] {
     let mut sha3 = Sha3_256::new();
-    Update::update(&mut sha3, &s);
-    sha3.finalize().to_vec()
+    Update::update(&mut sha3, s);
+    sha3.finalize().into()
 }
 
-pub fn j(s: Vec<u8>) -> Vec<u8> {
+pub fn j(s: &[u8]) -> [u8; 32] {
     let mut shake256 = Shake256::default();
-    shake256.update(&s);
-    let mut output = vec![0u8; 32];
+    shake256.update(s);
+    let mut output = [0u8; 32];
     shake256.finalize_xof().read(&mut output);
     output
 }
 
-pub fn g(s: Vec<u8>) -> (Vec<u8>, Vec<u8>) {
+pub fn g(s: &[u8]) -> ([u8; 32], [u8; 32]) {
     let mut sha3 = Sha3_512::new();
-    Update::update(&mut sha3, &s);
-    let output = sha3.finalize().to_vec();
-    (output[0..32].to_vec(), output[32..64].to_vec())
+    Update::update(&mut sha3, s);
+    let mut output = sha3.finalize();
+    let mut halves = ([0u8; 32], [0u8; 32]);
+    halves.0.copy_from_slice(&output[0..32]);
+    halves.1.copy_from_slice(&output[32..64]);
+    output.as_mut_slice().zeroize();
+    halves
+}
+
+// Constant-time helpers, used for the implicit rejection in decapsulation
+// Returns 0xff if a == b and 0x00 otherwise, reading every byte regardless of where they differ
+pub fn ct_eq(a: &[u8], b: &[u8]) -> u8 {
+    if a.len() != b.len() {
+        return 0;
+    }
+    let mut diff = 0u8;
+    for i in 0..a.len() {
+        diff |= a[i] ^ b[i];
+    }
+    let diff = core::hint::black_box(diff) as u16;
+    (diff.wrapping_sub(1) >> 8) as u8
+}
+
+// Replaces a with b where mask is 0xff and keeps a where mask is 0x00, without branching
+pub fn ct_select(a: &mut [u8], b: &[u8], mask: u8) {
+    let mask = core::hint::black_box(mask);
+    for i in 0..a.len() {
+        a[i] ^= mask & (a[i] ^ b[i]);
+    }
+}
+
+// Generate random bytes, from the operating system's RNG with the std feature
+#[cfg(feature = "std")]
+pub fn random_bytes(n: usize) -> Result<std::vec::Vec<u8>, Error> {
+    let mut bytes = std::vec![0u8; n];
+    random_bytes_with_rng(&mut rand::thread_rng(), &mut bytes)?;
+    Ok(bytes)
 }
 
-// Generate random bytes
-pub fn random_bytes(n: usize) -> Vec<u8> {
-    let mut rng = rand::thread_rng();
-    (0..n).map(|_| rng.gen::<u8>()).collect()
+pub fn random_bytes_with_rng<R: RngCore + CryptoRng>(rng: &mut R, bytes: &mut [u8]) -> Result<(), Error> {
+    rng.try_fill_bytes(bytes).map_err(|_| Error::Rng)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The division-based formulas compress and decompress used before, copied verbatim as the
+    // reference for the exhaustive tests
+    fn field_reduce(x: u16) -> u16 {
+        let wx = x.wrapping_sub(3329);
+        wx.wrapping_add((wx >> 15).wrapping_mul(3329))
+    }
+
+    fn div_and_round(dividend: u32, divisor: u32) -> u16 {
+        field_reduce(((dividend + (divisor >> 1)) / divisor) as u16)
+    }
+
+    #[test]
+    fn test_compress_exhaustive() {
+        for d in 1..=11u8 {
+            for x in 0..3329u16 {
+                // The old compress returned round(2^d / q * x) without the final reduction mod 2^d,
+                // i.e. 2^d instead of 0 for x close to q
+                let expected = div_and_round((x as u32) << d, 3329) % (1 << d);
+                let mut v = [x];
+                compress(&mut v, d);
+                assert_eq!(v, [expected], "x = {}, d = {}", x, d);
+            }
+        }
+    }
+
+    #[test]
+    fn test_decompress_exhaustive() {
+        for d in 1..=11u8 {
+            for y in 0..(1u16 << d) {
+                let expected = div_and_round((y as u32) * 3329, 1u32 << d);
+                let mut v = [y];
+                decompress(&mut v, d);
+                assert_eq!(v, [expected], "y = {}, d = {}", y, d);
+            }
+        }
+    }
+
+    #[test]
+    fn test_ct_eq() {
+        assert_eq!(ct_eq(&[1, 2, 3], &[1, 2, 3]), 0xff);
+        assert_eq!(ct_eq(&[1, 2, 3], &[0, 2, 3]), 0x00);
+        assert_eq!(ct_eq(&[1, 2, 3], &[1, 2, 0x83]), 0x00);
+        assert_eq!(ct_eq(&[1, 2, 3], &[1, 2]), 0x00);
+    }
+
+    #[test]
+    fn test_ct_select() {
+        let mut a = [1, 2, 3];
+        ct_select(&mut a, &[4, 5, 6], 0x00);
+        assert_eq!(a, [1, 2, 3]);
+        ct_select(&mut a, &[4, 5, 6], 0xff);
+        assert_eq!(a, [4, 5, 6]);
+    }
 }
@@ -1,124 +1,243 @@
 use crate::encode::*;
-use crate::field::FieldElement as FF;
 use crate::helper::*;
 use crate::matrix::*;
 use crate::poly::*;
-use crate::constant::{K, ETA1, ETA2, DU, DV};
+use crate::constant::N;
+use crate::field::FieldElement as FF;
+use crate::params::ParameterSet;
+use crate::error::Error;
+use crate::masked::{MaskedPolynomial, masked_compress_message};
+use crate::shuffle::Permutation;
+use crate::locked::LockedBox;
+use rand::{CryptoRng, RngCore};
+use zeroize::{Zeroize, Zeroizing};
+
+// Â with Â[i][j] = SampleNTT(rho || j || i)
+fn sample_matrix<P: ParameterSet>(rho: &[u8]) -> Result<P::PolyMatrix, Error> {
+    let mut a = P::PolyMatrix::from_fn(|_| P::PolyVec::from_fn(|_| Polynomial::zero_polynomial()));
+    for i in 0..P::K {
+        for j in 0..P::K {
+            a.as_mut()[i].as_mut()[j] = sample_ntt(rho, j as u8, i as u8)?;
+        }
+    }
+    Ok(a)
+}
 
-// Algorithm 13: Uses randomness to generate an encryption key and a corresponding decryption key.
-pub fn kpke_key_gen(mut bytes: Vec<u8>) -> (Vec<u16>, Vec<u16>) {
-    bytes.push(K as u8);
-    let (rho, sigma) = g(bytes);
-    let mut n = 0;
+// SamplePolyCBD_eta(PRF_eta(s, n))
+fn sample_cbd(eta: usize, s: &[u8], n: u8) -> Result<Polynomial, Error> {
+    let mut bytes = Zeroizing::new([0u8; 64 * 3]);
+    prf(s, n, &mut bytes[..64 * eta]);
+    sample_poly_cbd(&bytes[..64 * eta], eta)
+}
 
-    let mut a = Matrix::zero_matrix(K, K);
-    let mut s = Vec::new();
-    let mut e = Vec::new();
+// K polynomials sampled with consecutive PRF counters starting at n
+fn sample_vector<P: ParameterSet>(eta: usize, s: &[u8], n: &mut u8) -> Result<P::PolyVec, Error> {
+    let mut v = P::PolyVec::from_fn(|_| Polynomial::zero_polynomial());
+    for i in 0..P::K {
+        v.as_mut()[i] = sample_cbd(eta, s, *n)?;
+        *n += 1;
+    }
+    Ok(v)
+}
 
-    for i in 0..K {
-        for j in 0..K {
-            a.matrix[i][j] = sample_ntt(rho.clone(), j as u8, i as u8);
-        }
+// ByteDecode_12 of 384 bytes into a polynomial
+fn decode_polynomial(bytes: &[u8]) -> Result<Polynomial, Error> {
+    let mut f = Zeroizing::new([0u16; N]);
+    bytes_decode(12, bytes, &mut f)?;
+    Ok(Polynomial::from_list(&f))
+}
+
+// Algorithm 13: Uses randomness to generate an encryption key and a corresponding decryption key.
+// ek_pke (384k + 32 bytes) and dk_pke (384k bytes) are written to the caller's buffers.
+pub fn kpke_key_gen<P: ParameterSet>(d: &[u8], ek_pke: &mut [u8], dk_pke: &mut [u8]) -> Result<(), Error> {
+    if d.len() != 32 || ek_pke.len() != P::EK_LEN || dk_pke.len() != 384 * P::K {
+        return Err(Error::InvalidLength);
     }
+    let mut bytes = Zeroizing::new([0u8; 33]);
+    bytes[..32].copy_from_slice(d);
+    bytes[32] = P::K as u8;
+    let (rho, sigma) = g(&*bytes);
+    let sigma = Zeroizing::new(sigma);
+    let mut n = 0;
 
-    for _ in 0..K {
-        s.push(sample_poly_cbd(prf(ETA1, sigma.clone(), n), ETA1).ntt());
-        n += 1;
+    let a = sample_matrix::<P>(&rho)?;
+    let mut s = sample_vector::<P>(P::ETA1, &*sigma, &mut n)?;
+    let mut e = sample_vector::<P>(P::ETA1, &*sigma, &mut n)?;
+    for i in 0..P::K {
+        s.as_mut()[i] = s.as_ref()[i].clone().ntt();
+        e.as_mut()[i] = e.as_ref()[i].clone().ntt();
     }
 
-    for _ in 0..K {
-        e.push(sample_poly_cbd(prf(ETA1, sigma.clone(), n), ETA1).ntt());
-        n += 1;
+    let mut t = P::PolyVec::from_fn(|_| Polynomial::zero_polynomial());
+    mul(a.as_ref(), s.as_ref(), t.as_mut());
+    add(t.as_mut(), e.as_ref());
+    for (i, t_i) in t.as_ref().iter().enumerate() {
+        bytes_encode(12, &t_i.list(), &mut ek_pke[384 * i..384 * (i + 1)])?;
     }
+    ek_pke[384 * P::K..].copy_from_slice(&rho);
 
-    let t = add(mul(&a, s.clone()), e.clone());
-    let mut ek_pke = Vec::new();
-    for i in 0..K {
-        let temp: Vec<u16> = bytes_encode(12, t[i].coeffs.iter().map(|x| x.to_int()).collect());
-        ek_pke.extend(temp);
+    for (i, s_i) in s.as_ref().iter().enumerate() {
+        bytes_encode(12, &Zeroizing::new(s_i.list()), &mut dk_pke[384 * i..384 * (i + 1)])?;
     }
-    ek_pke.extend(rho.iter().map(|x| *x as u16));
+    Ok(())
+}
 
-    let mut pk_pke = Vec::new();
-    for i in 0..K {
-        let temp: Vec<u16> = bytes_encode(12, s[i].coeffs.iter().map(|x| x.to_int()).collect());
-        pk_pke.extend(temp);
+// Lines 2-8 of Algorithm 14: decodes t̂ from ek_pke and samples Â from rho
+pub fn kpke_expand_ek<P: ParameterSet>(ek_pke: &[u8]) -> Result<(P::PolyVec, P::PolyMatrix), Error> {
+    if ek_pke.len() != P::EK_LEN {
+        return Err(Error::InvalidLength);
+    }
+    let mut t = P::PolyVec::from_fn(|_| Polynomial::zero_polynomial());
+    for i in 0..P::K {
+        t.as_mut()[i] = decode_polynomial(&ek_pke[i * 384..(i + 1) * 384])?;
     }
+    let rho = &ek_pke[384 * P::K..384 * P::K + 32];
+    Ok((t, sample_matrix::<P>(rho)?))
+}
 
-    (ek_pke, pk_pke)
+// Line 5 of Algorithm 15: decodes ŝ from dk_pke into s, in place so that no copy of ŝ is left on the stack
+pub fn kpke_expand_dk<P: ParameterSet>(dk_pke: &[u8], s: &mut P::PolyVec) -> Result<(), Error> {
+    if dk_pke.len() != 384 * P::K {
+        return Err(Error::InvalidLength);
+    }
+    let mut f = Zeroizing::new([0u16; N]);
+    for (i, s_i) in s.as_mut().iter_mut().enumerate() {
+        bytes_decode(12, &dk_pke[384 * i..384 * (i + 1)], &mut f)?;
+        for (coeff, x) in s_i.coeffs.iter_mut().zip(f.iter()) {
+            *coeff = FF(*x);
+        }
+    }
+    Ok(())
 }
 
 // Algorithm 14: Uses the encryption key to encrypt a plaintext message using the randomness 𝑟.
-pub fn kpke_enc(ek_pke: Vec<u16>, m: Vec<u16>, r: Vec<u8>) -> Vec<u16> {
-    let mut n = 0;
-    let mut t: Vec<Polynomial> = Vec::new();
+// The ciphertext (CT_LEN bytes) is written to c.
+pub fn kpke_enc<P: ParameterSet>(ek_pke: &[u8], m: &[u8], r: &[u8], c: &mut [u8]) -> Result<(), Error> {
+    let (t, a) = kpke_expand_ek::<P>(ek_pke)?;
+    kpke_enc_expanded::<P>(&t, &a, m, r, c)
+}
 
-    for i in 0..K {
-        let temp: Polynomial = Polynomial::new(bytes_decode(12, ek_pke.clone()[i * 384..(i + 1) * 384].to_vec()).iter().map(|x| FF(*x)).collect());
-        t.push(temp);
+// Algorithm 14 with t̂ and Â already expanded by kpke_expand_ek
+pub fn kpke_enc_expanded<P: ParameterSet>(t: &P::PolyVec, a: &P::PolyMatrix, m: &[u8], r: &[u8], c: &mut [u8]) -> Result<(), Error> {
+    if m.len() != 32 || r.len() != 32 || c.len() != P::CT_LEN {
+        return Err(Error::InvalidLength);
     }
-    let rho: Vec<u8> = ek_pke.clone()[384 * K..384 * K + 32].iter().map(|x| *x as u8).collect();
-
-    let mut a = Matrix::zero_matrix(K, K);
-    let mut y = Vec::new();
-    let mut e1 = Vec::new();
-    let mut u = Vec::new();
-
-    for i in 0..K {
-        for j in 0..K {
-            a.matrix[i][j] = sample_ntt(rho.clone(), j as u8, i as u8);
-        }
+    let mut n = 0;
+    let mut y = sample_vector::<P>(P::ETA1, r, &mut n)?;
+    let e1 = sample_vector::<P>(P::ETA2, r, &mut n)?;
+    let e2 = sample_cbd(P::ETA2, r, n)?;
+    for i in 0..P::K {
+        y.as_mut()[i] = y.as_ref()[i].clone().ntt();
     }
 
-    for _ in 0..K {
-        y.push(sample_poly_cbd(prf(ETA1, r.clone(), n), ETA1).ntt());
-        n += 1;
+    let mut u = P::PolyVec::from_fn(|_| Polynomial::zero_polynomial());
+    mul_transpose(a.as_ref(), y.as_ref(), u.as_mut());
+    for i in 0..P::K {
+        u.as_mut()[i] = u.as_ref()[i].clone().intt() + e1.as_ref()[i].clone();
+    }
+    let mut mu = Zeroizing::new([0u16; N]);
+    bytes_decode(1, m, &mut mu)?;
+    decompress(&mut *mu, 1);
+    let nuy = Polynomial::from_list(&mu);
+
+    let v = vec_mul(t.as_ref(), y.as_ref()).intt() + e2 + nuy;
+
+    let (c1, c2) = c.split_at_mut(32 * P::DU * P::K);
+    for (i, u_i) in u.as_ref().iter().enumerate() {
+        let mut w = u_i.list();
+        compress(&mut w, P::DU as u8);
+        bytes_encode(P::DU, &w, &mut c1[32 * P::DU * i..32 * P::DU * (i + 1)])?;
     }
+    let mut w = v.list();
+    compress(&mut w, P::DV as u8);
+    bytes_encode(P::DV, &w, c2)
+}
 
-    for _ in 0..K {
-        e1.push(sample_poly_cbd(prf(ETA2, r.clone(), n), ETA2));
-        n += 1;
+// Decodes c = c1 || c2 into u and v, as in Algorithm 15
+fn decode_ciphertext<P: ParameterSet>(c: &[u8]) -> Result<(P::PolyVec, Polynomial), Error> {
+    let c1 = &c[0..32 * P::DU * P::K];
+    let c2 = &c[32 * P::DU * P::K..32 * (P::DU * P::K + P::DV)];
+    let mut u = P::PolyVec::from_fn(|_| Polynomial::zero_polynomial());
+    let mut w = [0u16; N];
+    for i in 0..P::K {
+        bytes_decode(P::DU, &c1[32 * P::DU * i..32 * P::DU * (i + 1)], &mut w)?;
+        decompress(&mut w, P::DU as u8);
+        u.as_mut()[i] = Polynomial::from_list(&w);
     }
 
-    let e2 = sample_poly_cbd(prf(ETA2, r.clone(), n), ETA2);
+    bytes_decode(P::DV, c2, &mut w)?;
+    decompress(&mut w, P::DV as u8);
+    Ok((u, Polynomial::from_list(&w)))
+}
 
-    let ay = mul(&a.transpose(), y.clone());
-    for i in 0..K {
-        u.push(ay[i].clone().intt() + e1[i].clone());
-    }
-    let nuy = Polynomial::new(decompress(bytes_decode(1, m), 1).iter().map(|x| FF(*x)).collect());
+// ByteEncode_1(Compress_1(w)) into the 32-byte message m
+fn encode_message(w: &Polynomial, m: &mut [u8]) -> Result<(), Error> {
+    let mut list = Zeroizing::new(w.list());
+    compress(&mut *list, 1);
+    bytes_encode(1, &list, m)
+}
 
-    let v = vec_mul(t.clone(), y.clone()).intt() + e2.clone() + nuy.clone();
-    
-    let mut c1: Vec<u16> = Vec::new();
-    for i in 0..K {
-        let temp: Vec<u16> = bytes_encode(DU, compress(u[i].coeffs.iter().map(|x| x.to_int()).collect(), DU as u8));
-        c1.extend(temp);
+// Algorithm 15: Uses the decryption key to decrypt a ciphertext.
+// The 32-byte message is written to m. ŝ is decoded into a LockedBox.
+pub fn kpke_dec<P: ParameterSet>(pk_pke: &[u8], c: &[u8], m: &mut [u8]) -> Result<(), Error> {
+    let mut s = LockedBox::new(P::PolyVec::from_fn(|_| Polynomial::zero_polynomial()))?;
+    kpke_expand_dk::<P>(pk_pke, &mut s)?;
+    kpke_dec_expanded::<P>(&s, c, m)
+}
+
+// Algorithm 15 with ŝ already decoded by kpke_expand_dk
+pub fn kpke_dec_expanded<P: ParameterSet>(s: &P::PolyVec, c: &[u8], m: &mut [u8]) -> Result<(), Error> {
+    if c.len() != P::CT_LEN || m.len() != 32 {
+        return Err(Error::InvalidLength);
+    }
+    let (mut u, v) = decode_ciphertext::<P>(c)?;
+    for i in 0..P::K {
+        u.as_mut()[i] = u.as_ref()[i].clone().ntt();
     }
-    let c2 = bytes_encode(DV, compress(v.coeffs.iter().map(|x| x.to_int()).collect(), DV as u8));
-    [c1, c2].concat()
+
+    let w = v - vec_mul(s.as_ref(), u.as_ref()).intt();
+    encode_message(&w, m)
 }
 
-// Algorithm 15: Uses the decryption key to decrypt a ciphertext.
-pub fn kpke_dec(pk_pke: Vec<u16>, c: Vec<u16>) -> Vec<u16> {
-    let c1 = c.clone()[0..32 * DU * K].to_vec();
-    let c2 = c.clone()[32 * DU * K..32 * (DU * K + DV)].to_vec();
-    let mut u = Vec::new();
-    for i in 0..K {
-        u.push(Polynomial::new(decompress(bytes_decode(DU, c1.clone()[32 * DU * i..32 * DU * (i + 1)].to_vec()), DU as u8).iter().map(|x| FF(*x)).collect()));
+// Algorithm 15 with first-order masking: each ŝ_i, decoded by kpke_expand_dk, is split into two arithmetic
+// shares before use, multiply_ntt, intt and Compress_1 run share-wise, see masked.rs.
+// The message shares are recombined on return, as G and the re-encryption are not masked.
+pub fn kpke_dec_masked<P: ParameterSet, R: RngCore + CryptoRng>(s: &P::PolyVec, c: &[u8], m: &mut [u8], rng: &mut R) -> Result<(), Error> {
+    if c.len() != P::CT_LEN || m.len() != 32 {
+        return Err(Error::InvalidLength);
     }
+    let (u, v) = decode_ciphertext::<P>(c)?;
+    let mut w = MaskedPolynomial::zero_polynomial();
 
-    let v = Polynomial::new(decompress(bytes_decode(DV, c2), DV as u8).iter().map(|x| FF(*x)).collect());
-    let mut s: Vec<Polynomial> = Vec::new();
+    for (s_i, u_i) in s.as_ref().iter().zip(u.as_ref()) {
+        let s_i = MaskedPolynomial::split(s_i, rng)?;
+        w = w + s_i.multiply_ntt(&u_i.clone().ntt());
+    }
 
-    for i in 0..K {
-        s.push(Polynomial::new(bytes_decode(12, pk_pke.clone()[384 * i..384 * (i + 1)].to_vec()).iter().map(|x| FF(*x)).collect()));
+    let w = w.intt().sub_from(v);
+    let (mut m0, mut m1) = masked_compress_message(&w, rng)?;
+    for i in 0..32 {
+        m[i] = m0[i] ^ m1[i];
     }
-    for i in 0..K {
-        u[i] = u[i].clone().ntt();
+    m0.zeroize();
+    m1.zeroize();
+    Ok(())
+}
+
+// Algorithm 15 with shuffling, on ŝ decoded by kpke_expand_dk: every NTT, base case multiplication and
+// inverse NTT runs in the order of a fresh random permutation, see shuffle.rs
+pub fn kpke_dec_shuffled<P: ParameterSet, R: RngCore + CryptoRng>(s: &P::PolyVec, c: &[u8], m: &mut [u8], rng: &mut R) -> Result<(), Error> {
+    if c.len() != P::CT_LEN || m.len() != 32 {
+        return Err(Error::InvalidLength);
+    }
+    let (u, v) = decode_ciphertext::<P>(c)?;
+    let mut su = Polynomial::zero_polynomial();
+
+    for (s_i, u_i) in s.as_ref().iter().zip(u.as_ref()) {
+        let u_i = u_i.clone().ntt_shuffled(&Permutation::random(rng)?);
+        su = su + Polynomial::multiply_ntt_shuffled(s_i, &u_i, &Permutation::random(rng)?);
     }
 
-    let w = v - vec_mul(s.clone(), u.clone()).intt();
-    let m = bytes_encode(1, compress(w.coeffs.iter().map(|x| x.to_int()).collect(), 1));
-    m
+    let w = v - su.intt_shuffled(&Permutation::random(rng)?);
+    encode_message(&w, m)
 }
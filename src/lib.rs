@@ -1,3 +1,8 @@
+#![no_std]
+
+#[cfg(any(feature = "std", test))]
+extern crate std;
+
 pub mod field;
 
 pub mod poly;
@@ -12,4 +17,20 @@ pub mod kpke;
 
 pub mod mlkem;
 
-pub mod constant;
\ No newline at end of file
+pub mod constant;
+
+pub mod params;
+
+pub mod types;
+
+pub mod error;
+
+pub mod debug;
+
+pub mod selftest;
+
+pub mod locked;
+
+pub mod masked;
+
+pub mod shuffle;
\ No newline at end of file
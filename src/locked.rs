@@ -0,0 +1,193 @@
+use core::ops::{Deref, DerefMut};
+#[cfg(all(feature = "mlock", target_os = "linux"))]
+use core::ptr::NonNull;
+use zeroize::Zeroize;
+use crate::error::Error;
+
+// Storage for secret values that is wiped on drop. With the `mlock` feature on Linux the
+// value lives in its own mlock()ed pages, excluded from core dumps and surrounded by PROT_NONE
+// guard pages; otherwise it is stored inline, without a heap allocation. Inline storage moves
+// with the LockedBox, and a move leaves the old bytes behind unwiped, so keys that must leave no
+// copies should use `mlock` or stay in one place. It is not Clone, as allocating locked memory can
+// fail; the key types copy themselves with a fallible try_clone instead.
+pub struct LockedBox<T: Zeroize> {
+    #[cfg(all(feature = "mlock", target_os = "linux"))]
+    value: NonNull<T>,
+    #[cfg(all(feature = "mlock", target_os = "linux"))]
+    _mapping: pages::Mapping,
+    #[cfg(not(all(feature = "mlock", target_os = "linux")))]
+    value: T,
+}
+
+#[cfg(all(feature = "mlock", target_os = "linux"))]
+unsafe impl<T: Zeroize + Send> Send for LockedBox<T> {}
+
+#[cfg(all(feature = "mlock", target_os = "linux"))]
+unsafe impl<T: Zeroize + Sync> Sync for LockedBox<T> {}
+
+#[cfg(all(feature = "mlock", target_os = "linux"))]
+impl<T: Zeroize> LockedBox<T> {
+    pub fn new(value: T) -> Result<Self, Error> {
+        let mapping = pages::Mapping::new(core::mem::size_of::<T>())?;
+        let ptr = mapping.place::<T>();
+        unsafe { ptr.write(value) };
+        Ok(LockedBox { value: NonNull::new(ptr).ok_or(Error::LockedMemory)?, _mapping: mapping })
+    }
+}
+
+#[cfg(not(all(feature = "mlock", target_os = "linux")))]
+impl<T: Zeroize> LockedBox<T> {
+    pub fn new(value: T) -> Result<Self, Error> {
+        Ok(LockedBox { value })
+    }
+}
+
+impl<T: Zeroize> Drop for LockedBox<T> {
+    fn drop(&mut self) {
+        #[cfg(all(feature = "mlock", target_os = "linux"))]
+        unsafe {
+            self.value.as_mut().zeroize();
+            core::ptr::drop_in_place(self.value.as_ptr());
+        }
+        #[cfg(not(all(feature = "mlock", target_os = "linux")))]
+        self.value.zeroize();
+    }
+}
+
+impl<T: Zeroize> Deref for LockedBox<T> {
+    type Target = T;
+
+    #[cfg(all(feature = "mlock", target_os = "linux"))]
+    fn deref(&self) -> &T {
+        unsafe { self.value.as_ref() }
+    }
+
+    #[cfg(not(all(feature = "mlock", target_os = "linux")))]
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T: Zeroize> DerefMut for LockedBox<T> {
+    #[cfg(all(feature = "mlock", target_os = "linux"))]
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.value.as_mut() }
+    }
+
+    #[cfg(not(all(feature = "mlock", target_os = "linux")))]
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+#[cfg(all(feature = "mlock", target_os = "linux"))]
+mod pages {
+    use crate::error::Error;
+
+    // guard page | data pages (locked) | guard page
+    pub struct Mapping {
+        base: *mut u8,
+        page: usize,
+        data_len: usize,
+    }
+
+    impl Mapping {
+        pub fn new(size: usize) -> Result<Mapping, Error> {
+            let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+            let data_len = size.max(1).div_ceil(page) * page;
+            let total = data_len + 2 * page;
+            let base = unsafe {
+                libc::mmap(core::ptr::null_mut(), total, libc::PROT_READ | libc::PROT_WRITE, libc::MAP_PRIVATE | libc::MAP_ANONYMOUS, -1, 0)
+            };
+            if base == libc::MAP_FAILED {
+                return Err(Error::LockedMemory);
+            }
+            let mapping = Mapping { base: base as *mut u8, page, data_len };
+            let data = mapping.data();
+            unsafe {
+                if libc::mprotect(base, page, libc::PROT_NONE) != 0
+                    || libc::mprotect(data.add(data_len) as *mut libc::c_void, page, libc::PROT_NONE) != 0
+                    || libc::mlock(data as *const libc::c_void, data_len) != 0
+                {
+                    return Err(Error::LockedMemory);
+                }
+                libc::madvise(data as *mut libc::c_void, data_len, libc::MADV_DONTDUMP);
+            }
+            Ok(mapping)
+        }
+
+        fn data(&self) -> *mut u8 {
+            unsafe { self.base.add(self.page) }
+        }
+
+        // Places T at the end of the data pages, so an overflow runs into the trailing guard page
+        pub fn place<T>(&self) -> *mut T {
+            let offset = (self.data_len - core::mem::size_of::<T>()) & !(core::mem::align_of::<T>() - 1);
+            unsafe { self.data().add(offset) as *mut T }
+        }
+    }
+
+    impl Drop for Mapping {
+        fn drop(&mut self) {
+            unsafe {
+                libc::munlock(self.data() as *const libc::c_void, self.data_len);
+                libc::munmap(self.base as *mut libc::c_void, self.data_len + 2 * self.page);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locked_box() {
+        let mut a = LockedBox::new([1u8; 100]).unwrap();
+        a[99] = 2;
+        assert_eq!(a[0], 1);
+        assert_eq!(a[99], 2);
+    }
+
+    #[cfg(all(feature = "mlock", target_os = "linux"))]
+    fn locked_kb() -> usize {
+        let status = std::fs::read_to_string("/proc/self/status").unwrap();
+        let line = status.lines().find(|l| l.starts_with("VmLck:")).unwrap();
+        line.split_whitespace().nth(1).unwrap().parse().unwrap()
+    }
+
+    #[cfg(all(feature = "mlock", target_os = "linux"))]
+    #[test]
+    fn test_locked_box_is_locked() {
+        let a = LockedBox::new([0u8; 64 * 1024]).unwrap();
+        assert!(locked_kb() >= 64);
+        drop(a);
+    }
+
+    static WIPED: core::sync::atomic::AtomicBool = core::sync::atomic::AtomicBool::new(false);
+
+    struct Secret(u8);
+
+    impl Zeroize for Secret {
+        fn zeroize(&mut self) {
+            self.0 = 0;
+            WIPED.store(true, core::sync::atomic::Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_locked_box_wiped_on_drop() {
+        let a = LockedBox::new(Secret(7)).unwrap();
+        assert_eq!(a.0, 7);
+        assert!(!WIPED.load(core::sync::atomic::Ordering::SeqCst));
+        drop(a);
+        assert!(WIPED.load(core::sync::atomic::Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_locked_box_alignment() {
+        let a = LockedBox::new([7u64; 3]).unwrap();
+        assert_eq!(&*a as *const [u64; 3] as usize % core::mem::align_of::<u64>(), 0);
+        assert_eq!(*a, [7u64; 3]);
+    }
+}
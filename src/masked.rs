@@ -0,0 +1,182 @@
+use crate::field::FieldElement as FF;
+use crate::poly::Polynomial;
+use crate::constant::{N, Q};
+use crate::error::Error;
+use rand::{CryptoRng, RngCore};
+use zeroize::Zeroize;
+use core::ops::Add;
+
+// First-order masking for decryption, against power analysis of kpke_dec.
+// A secret is held as two shares, arithmetic (x = x0 + x1 mod q) or Boolean (x = x0 ^ x1),
+// and no intermediate value depends on both shares of the same secret.
+
+type BooleanShares = (u16, u16);
+
+fn random_u16<R: RngCore + CryptoRng>(rng: &mut R) -> Result<u16, Error> {
+    let mut bytes = [0u8; 2];
+    rng.try_fill_bytes(&mut bytes).map_err(|_| Error::Rng)?;
+    Ok(u16::from_le_bytes(bytes))
+}
+
+// Uniform element of Z_q by rejection sampling, which only depends on the random input
+fn random_field_element<R: RngCore + CryptoRng>(rng: &mut R) -> Result<FF, Error> {
+    loop {
+        let x = random_u16(rng)? & 0xfff;
+        if x < Q {
+            return Ok(FF(x));
+        }
+    }
+}
+
+// Two arithmetic shares of a polynomial, f = shares[0] + shares[1]
+pub struct MaskedPolynomial {
+    pub shares: [Polynomial; 2],
+}
+
+impl MaskedPolynomial {
+    pub fn zero_polynomial() -> MaskedPolynomial {
+        MaskedPolynomial { shares: [Polynomial::zero_polynomial(), Polynomial::zero_polynomial()] }
+    }
+
+    // Splits f into f - r and r for a uniformly random r
+    pub fn split<R: RngCore + CryptoRng>(f: &Polynomial, rng: &mut R) -> Result<MaskedPolynomial, Error> {
+        let mut f0 = Polynomial::zero_polynomial();
+        let mut r = Polynomial::zero_polynomial();
+        for i in 0..N {
+            r.coeffs[i] = random_field_element(rng)?;
+            f0.coeffs[i] = f.coeffs[i] - r.coeffs[i];
+        }
+        Ok(MaskedPolynomial { shares: [f0, r] })
+    }
+
+    pub fn ntt(self) -> MaskedPolynomial {
+        let [f0, f1] = self.shares;
+        MaskedPolynomial { shares: [f0.ntt(), f1.ntt()] }
+    }
+
+    pub fn intt(self) -> MaskedPolynomial {
+        let [f0, f1] = self.shares;
+        MaskedPolynomial { shares: [f0.intt(), f1.intt()] }
+    }
+
+    // Product with a public polynomial in the NTT domain, linear in the shares
+    pub fn multiply_ntt(&self, g: &Polynomial) -> MaskedPolynomial {
+        MaskedPolynomial {
+            shares: [Polynomial::multiply_ntt(&self.shares[0], g), Polynomial::multiply_ntt(&self.shares[1], g)],
+        }
+    }
+
+    // Computes v - f for a public polynomial v
+    pub fn sub_from(self, v: Polynomial) -> MaskedPolynomial {
+        let [f0, f1] = self.shares;
+        MaskedPolynomial { shares: [v - f0, Polynomial::zero_polynomial() - f1] }
+    }
+
+    pub fn unmask(&self) -> Polynomial {
+        self.shares[0].clone() + self.shares[1].clone()
+    }
+}
+
+impl Add<MaskedPolynomial> for MaskedPolynomial {
+    type Output = MaskedPolynomial;
+
+    fn add(self, other: MaskedPolynomial) -> MaskedPolynomial {
+        let [f0, f1] = self.shares;
+        let [g0, g1] = other.shares;
+        MaskedPolynomial { shares: [f0 + g0, f1 + g1] }
+    }
+}
+
+// ISW multiplication, (x0 ^ x1) & (y0 ^ y1), refreshed with a fresh random r
+fn sec_and<R: RngCore + CryptoRng>(x: BooleanShares, y: BooleanShares, rng: &mut R) -> Result<BooleanShares, Error> {
+    let r = random_u16(rng)?;
+    let z0 = (x.0 & y.0) ^ r;
+    // The cross terms are only ever combined with r, never with each other first
+    let t = core::hint::black_box(r ^ (x.0 & y.1));
+    let z1 = (x.1 & y.1) ^ (t ^ (x.1 & y.0));
+    Ok((z0, z1))
+}
+
+// Addition modulo 2^16 of Boolean-shared values, as a ripple-carry adder built from sec_and
+fn sec_add<R: RngCore + CryptoRng>(x: BooleanShares, y: BooleanShares, rng: &mut R) -> Result<BooleanShares, Error> {
+    let p = (x.0 ^ y.0, x.1 ^ y.1);
+    let g = sec_and(x, y, rng)?;
+    let mut c = (0, 0);
+    for _ in 0..15 {
+        let t = sec_and(c, p, rng)?;
+        c = ((g.0 ^ t.0) << 1, (g.1 ^ t.1) << 1);
+    }
+    Ok((p.0 ^ c.0, p.1 ^ c.1))
+}
+
+// Boolean shares of [s < t] for s < 2^15, the sign bit of s - t
+fn sec_less_than<R: RngCore + CryptoRng>(s: BooleanShares, t: u16, rng: &mut R) -> Result<BooleanShares, Error> {
+    let d = sec_add(s, (t.wrapping_neg(), 0), rng)?;
+    Ok((d.0 >> 15, d.1 >> 15))
+}
+
+// Masked Compress_q(x, 1): takes arithmetic shares a0 + a1 = x mod q and returns Boolean shares of the bit,
+// which is 1 for x in [833, 2496]
+fn masked_compress_coeff<R: RngCore + CryptoRng>(a0: u16, a1: u16, rng: &mut R) -> Result<BooleanShares, Error> {
+    // Boolean sharings of a0 and a1, added as integers: s = a0 + a1 lies in [0, 2q)
+    let m0 = random_u16(rng)?;
+    let m1 = random_u16(rng)?;
+    let s = sec_add((a0 ^ m0, m0), (m1, a1 ^ m1), rng)?;
+    // s mod q in [833, 2496] iff s in [833, 2497) or s in [833 + q, 2497 + q)
+    let mut bit = (0, 0);
+    for t in [833, 2497, 833 + Q, 2497 + Q] {
+        let lt = sec_less_than(s, t, rng)?;
+        bit = (bit.0 ^ lt.0, bit.1 ^ lt.1);
+    }
+    Ok(bit)
+}
+
+// ByteEncode_1(Compress_1(w)) on a masked w, returning Boolean shares of the 32-byte message
+pub fn masked_compress_message<R: RngCore + CryptoRng>(w: &MaskedPolynomial, rng: &mut R) -> Result<([u8; 32], [u8; 32]), Error> {
+    let mut m0 = [0u8; 32];
+    let mut m1 = [0u8; 32];
+    for i in 0..N {
+        let mut bit = masked_compress_coeff(w.shares[0].coeffs[i].to_int(), w.shares[1].coeffs[i].to_int(), rng)?;
+        m0[i / 8] |= (bit.0 as u8) << (i % 8);
+        m1[i / 8] |= (bit.1 as u8) << (i % 8);
+        bit.zeroize();
+    }
+    Ok((m0, m1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helper::compress;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_masked_compress_coeff() {
+        let mut rng = StdRng::seed_from_u64(2);
+        for x in 0..Q {
+            let mut expected = [x];
+            compress(&mut expected, 1);
+            let expected = expected[0];
+            for a1 in [0, 1, x, Q - 1, random_field_element(&mut rng).unwrap().0] {
+                let a0 = (FF(x) - FF(a1)).to_int();
+                let bit = masked_compress_coeff(a0, a1, &mut rng).unwrap();
+                assert_eq!(bit.0 ^ bit.1, expected, "x = {}, a1 = {}", x, a1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_masked_polynomial() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let f = Polynomial::new(core::array::from_fn(|x| FF::new(x as u16 * 13)));
+        let g = Polynomial::new(core::array::from_fn(|x| FF::new(x as u16 * 7 + 1)));
+        let masked = MaskedPolynomial::split(&f, &mut rng).unwrap();
+        assert_ne!(masked.shares[0], f);
+        assert_eq!(masked.unmask(), f);
+
+        let product = masked.ntt().multiply_ntt(&g.clone().ntt()).intt();
+        assert_eq!(product.unmask(), f.clone() * g.clone());
+        assert_eq!(product.sub_from(g.clone()).unmask(), g.clone() - f * g);
+    }
+}
@@ -1,52 +1,98 @@
 use crate::poly::Polynomial;
+use crate::debug::DebugSecret;
+use core::fmt;
 
-#[derive(Debug, Clone, PartialEq)]
-pub struct Matrix {
-    pub matrix: Vec<Vec<Polynomial>>
+// Fixed-size storage for vectors [Polynomial; K] and matrices Matrix<K> of a parameter set,
+// see ParameterSet::PolyVec and ParameterSet::PolyMatrix
+pub trait FixedArray<T>: AsRef<[T]> + AsMut<[T]> + Clone {
+    fn from_fn<F: FnMut(usize) -> T>(f: F) -> Self;
 }
 
-impl Matrix {
-    pub fn init(matrix: Vec<Vec<Polynomial>>) -> Matrix {
+impl<T: Clone, const K: usize> FixedArray<T> for [T; K] {
+    fn from_fn<F: FnMut(usize) -> T>(f: F) -> Self {
+        core::array::from_fn(f)
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub struct Matrix<const K: usize> {
+    pub matrix: [[Polynomial; K]; K]
+}
+
+impl<const K: usize> fmt::Debug for Matrix<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Matrix").finish_non_exhaustive()
+    }
+}
+
+impl<const K: usize> DebugSecret for Matrix<K> {
+    fn fmt_secret(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rows = self.matrix.each_ref().map(|row| row.each_ref().map(|p| p.unredacted()));
+        f.debug_struct("Matrix").field("matrix", &rows).finish()
+    }
+}
+
+impl<const K: usize> Matrix<K> {
+    pub fn init(matrix: [[Polynomial; K]; K]) -> Matrix<K> {
         Matrix { matrix }
     }
 
-    pub fn zero_matrix(row: usize, column: usize) -> Matrix {
-        Matrix::init(vec![vec![Polynomial::zero_polynomial(); column]; row])
+    pub fn zero_matrix() -> Matrix<K> {
+        Matrix::init(core::array::from_fn(|_| core::array::from_fn(|_| Polynomial::zero_polynomial())))
     }
 
-    pub fn transpose(&self) -> Matrix {
-        let mut matrix = vec![vec![Polynomial::zero_polynomial(); self.matrix.len()]; self.matrix[0].len()];
-        for i in 0..self.matrix.len() {
-            for j in 0..self.matrix[i].len() {
-                matrix[i][j] = self.matrix[j][i].clone();
-            }
-        }
-        Matrix::init(matrix)
+    pub fn transpose(&self) -> Matrix<K> {
+        Matrix::init(core::array::from_fn(|i| core::array::from_fn(|j| self.matrix[j][i].clone())))
     }
 }
 
-pub fn mul(a: &Matrix, b: Vec<Polynomial>) -> Vec<Polynomial> {
-    let mut c = vec![Polynomial::zero_polynomial(); a.matrix.len()];
-    for i in 0..a.matrix.len() {
-        for j in 0..a.matrix[i].len() {
-            c[i] = c[i].clone() + Polynomial::multiply_ntt(a.matrix[i][j].clone(), b[j].clone());
+impl<const K: usize> AsRef<[[Polynomial; K]]> for Matrix<K> {
+    fn as_ref(&self) -> &[[Polynomial; K]] {
+        &self.matrix
+    }
+}
+
+impl<const K: usize> AsMut<[[Polynomial; K]]> for Matrix<K> {
+    fn as_mut(&mut self) -> &mut [[Polynomial; K]] {
+        &mut self.matrix
+    }
+}
+
+impl<const K: usize> FixedArray<[Polynomial; K]> for Matrix<K> {
+    fn from_fn<F: FnMut(usize) -> [Polynomial; K]>(f: F) -> Self {
+        Matrix::init(core::array::from_fn(f))
+    }
+}
+
+// c = a b
+pub fn mul<V: AsRef<[Polynomial]>>(a: &[V], b: &[Polynomial], c: &mut [Polynomial]) {
+    for i in 0..a.len() {
+        c[i] = vec_mul(a[i].as_ref(), b);
+    }
+}
+
+// c = a^T b, without building the transpose
+pub fn mul_transpose<V: AsRef<[Polynomial]>>(a: &[V], b: &[Polynomial], c: &mut [Polynomial]) {
+    for (i, c_i) in c.iter_mut().enumerate() {
+        let mut sum = Polynomial::zero_polynomial();
+        for (a_j, b_j) in a.iter().zip(b) {
+            sum = sum + Polynomial::multiply_ntt(&a_j.as_ref()[i], b_j);
         }
+        *c_i = sum;
     }
-    c
 }
 
-pub fn add(a: Vec<Polynomial>, b: Vec<Polynomial>) -> Vec<Polynomial> {
-    let mut c = Vec::new();
+// a = a + b
+pub fn add(a: &mut [Polynomial], b: &[Polynomial]) {
     for i in 0..a.len() {
-        c.push(a[i].clone() + b[i].clone());
+        a[i] = a[i].clone() + b[i].clone();
     }
-    c
 }
 
-pub fn vec_mul(a: Vec<Polynomial>, b: Vec<Polynomial>) -> Polynomial {
+pub fn vec_mul(a: &[Polynomial], b: &[Polynomial]) -> Polynomial {
     let mut c = Polynomial::zero_polynomial();
     for i in 0..a.len() {
-        c = c.clone() + Polynomial::multiply_ntt(a[i].clone(), b[i].clone());
+        c = c + Polynomial::multiply_ntt(&a[i], &b[i]);
     }
     c
 }
\ No newline at end of file
@@ -1,77 +1,590 @@
 use crate::kpke::*;
-use crate::helper::{h, g, j, random_bytes};
-use crate::constant::K;
+use crate::encode::{bytes_encode, bytes_decode};
+use crate::helper::{h, g, j, ct_eq, ct_select, random_bytes_with_rng};
+use rand::{CryptoRng, RngCore};
+use zeroize::{Zeroize, Zeroizing};
+use crate::constant::N;
+use crate::params::{ByteArray, ParameterSet};
+use crate::types::{EncapsulationKey, DecapsulationKey, ExpandedDecapsulationKey, Ciphertext, SharedSecret};
+use crate::error::Error;
+use crate::selftest::ensure_self_test;
+use core::hint::black_box;
+
+// Input checking for encapsulation keys, Section 7.2: type check and modulus check
+pub fn validate_encapsulation_key<P: ParameterSet>(ek: &[u8]) -> Result<(), Error> {
+    if ek.len() != P::EK_LEN {
+        return Err(Error::InvalidLength);
+    }
+    let mut f = [0u16; N];
+    let mut encoded = [0u8; 384];
+    for i in 0..P::K {
+        let ek_i = &ek[384 * i..384 * (i + 1)];
+        bytes_decode(12, ek_i, &mut f)?;
+        bytes_encode(12, &f, &mut encoded)?;
+        if encoded[..] != ek_i[..] {
+            return Err(Error::ModulusCheck);
+        }
+    }
+    Ok(())
+}
+
+// Input checking for decapsulation, Section 7.3: ciphertext type check
+pub fn validate_ciphertext<P: ParameterSet>(c: &[u8]) -> Result<(), Error> {
+    if c.len() != P::CT_LEN {
+        return Err(Error::InvalidLength);
+    }
+    Ok(())
+}
+
+// Input checking for decapsulation, Section 7.3: decapsulation key type check and hash check
+pub fn validate_decapsulation_key<P: ParameterSet>(dk: &[u8]) -> Result<(), Error> {
+    let k = P::K;
+    if dk.len() != P::DK_LEN {
+        return Err(Error::InvalidLength);
+    }
+    if h(&dk[384*k..768*k+32]) != dk[768*k+32..768*k+64] {
+        return Err(Error::HashCheck);
+    }
+    Ok(())
+}
 
 // Algorithm 16:Uses randomness to generate an encapsulation key and a corresponding decapsulation key.
-pub fn keygen_internal(d: Vec<u8>, z: Vec<u16>) -> (Vec<u16>, Vec<u16>) {
-    let (ek_pke, dk_pke) = kpke_key_gen(d);
-    let ek = ek_pke.clone();
-    
-    let mut dk = dk_pke.clone();
-    dk.extend(ek.clone());
-    dk.extend(h(ek.clone().iter().map(|x| *x as u8).collect()).iter().map(|x| *x as u16));
-    dk.extend(z);
-    assert_eq!(ek.len(), 384*K+32);
-    assert_eq!(dk.len(), 768*K+96);
-    (ek, dk)
+// The seeds d and z are wiped before returning.
+pub(crate) fn keygen_internal<P: ParameterSet>(mut d: [u8; 32], mut z: [u8; 32]) -> Result<(EncapsulationKey<P>, DecapsulationKey<P>), Error> {
+    let result = keygen_from_seeds::<P>(&d, &z);
+    d.zeroize();
+    z.zeroize();
+    result
+}
+
+fn keygen_from_seeds<P: ParameterSet>(d: &[u8; 32], z: &[u8; 32]) -> Result<(EncapsulationKey<P>, DecapsulationKey<P>), Error> {
+    let k = P::K;
+    let mut ek = P::EncapsulationKeyBytes::zeroed();
+    let mut dk = Zeroizing::new(P::DecapsulationKeyBytes::zeroed());
+    let ek = ek.as_mut();
+    let dk_bytes = dk.as_mut();
+    kpke_key_gen::<P>(d, ek, &mut dk_bytes[..384*k])?;
+
+    dk_bytes[384*k..768*k+32].copy_from_slice(ek);
+    dk_bytes[768*k+32..768*k+64].copy_from_slice(&h(ek));
+    dk_bytes[768*k+64..].copy_from_slice(z);
+    let mut seed = [0u8; 64];
+    seed[..32].copy_from_slice(d);
+    seed[32..].copy_from_slice(z);
+    let dk = DecapsulationKey::from_bytes(dk.as_ref()).and_then(|dk| dk.with_seed(&seed));
+    seed.zeroize();
+    Ok((EncapsulationKey::from_bytes(ek)?, dk?))
 }
 
 // Algorithm 17: Uses the encapsulation key and randomness to generate a key and an associated ciphertext.
-pub fn encaps_internal(ek: Vec<u16>, m: Vec<u8>) -> (Vec<u8>, Vec<u16>) {
-    let mut m_ = m.clone();
-    m_.append(&mut h(ek.clone().iter().map(|x| *x as u8).collect()));
-    let (k, r) = g(m_.clone());
-    let c = kpke_enc(ek.clone(), m.iter().map(|x| *x as u16).collect(), r);
-    (k, c)
+// The randomness m is wiped before returning.
+pub(crate) fn encaps_internal<P: ParameterSet>(ek: &EncapsulationKey<P>, mut m: [u8; 32]) -> Result<(SharedSecret, Ciphertext<P>), Error> {
+    let mut c = P::CiphertextBytes::zeroed();
+    let mut k = Zeroizing::new([0u8; 32]);
+    let result = encaps_into_internal::<P>(ek, &m, &mut c, &mut k);
+    m.zeroize();
+    result?;
+    Ok((SharedSecret::from_bytes(*k), Ciphertext::from_bytes(c.as_ref())?))
+}
+
+// Algorithm 17 writing the ciphertext to c and the shared secret key to k
+fn encaps_into_internal<P: ParameterSet>(ek: &EncapsulationKey<P>, m: &[u8; 32], c: &mut P::CiphertextBytes, k: &mut [u8; 32]) -> Result<(), Error> {
+    validate_encapsulation_key::<P>(ek.as_bytes())?;
+    let mut m_ = Zeroizing::new([0u8; 64]);
+    m_[..32].copy_from_slice(m);
+    m_[32..].copy_from_slice(&h(ek.as_bytes()));
+    let (k_, r) = g(&*m_);
+    let (k_, r) = (Zeroizing::new(k_), Zeroizing::new(r));
+    kpke_enc::<P>(ek.as_bytes(), &m_[..32], &*r, c.as_mut())?;
+    k.copy_from_slice(&*k_);
+    Ok(())
 }
 
 // Algorithm 18: Uses the decapsulation key to produce a shared secret key from a ciphertext.
-pub fn decaps_internal(dk: Vec<u16>, c: Vec<u16>) -> Vec<u8> {
-    let dk_pke = dk.clone()[0..384*K].to_vec();
-    let ek_pke = dk.clone()[384*K..768*K+32].to_vec();
-    let mut h = dk.clone()[768*K+32..768*K+64].to_vec();
-    let mut z = dk.clone()[768*K+64..768*K+96].to_vec();
-    let m = kpke_dec(dk_pke, c.clone());
-    let mut m_ = m.clone();
-    m_.append(&mut h);
-    let (mut k_, r_) = g(m_.clone().iter().map(|x| *x as u8).collect());
-    z.append(&mut c.clone());
-    let kk = j(z.clone().iter().map(|x| *x as u8).collect());
-
-    let c_ = kpke_enc(ek_pke.clone(), m.iter().map(|x| *x as u16).collect(), r_);
-    if c != c_ {
-        k_ = kk;
-    } 
-    k_
+pub(crate) fn decaps_internal<P: ParameterSet>(dk: &DecapsulationKey<P>, c: &Ciphertext<P>) -> Result<SharedSecret, Error> {
+    decaps_with::<P, _>(dk, c, kpke_dec_expanded::<P>)
+}
+
+// Algorithm 18 with an expanded decapsulation key, reusing its ŝ, t̂, Â and H(ek)
+pub(crate) fn decaps_expanded_internal<P: ParameterSet>(dk: &ExpandedDecapsulationKey<P>, c: &Ciphertext<P>) -> Result<SharedSecret, Error> {
+    let c = c.as_bytes();
+    let candidates = decaps_candidates_with::<P, _, _>(
+        dk.h(),
+        dk.z(),
+        c,
+        |c, m| kpke_dec_expanded::<P>(dk.s_hat(), c, m),
+        |m, r, c_| kpke_enc_expanded::<P>(dk.t_hat(), dk.a_hat(), m, r, c_),
+    )?;
+    let mut k = Zeroizing::new([0u8; 32]);
+    select_key::<P>(c, candidates, &mut k);
+    Ok(SharedSecret::from_bytes(*k))
+}
+
+// Algorithm 18 with the masked K-PKE.Decrypt, see kpke_dec_masked
+pub(crate) fn decaps_masked_internal<P: ParameterSet, R: RngCore + CryptoRng>(dk: &DecapsulationKey<P>, c: &Ciphertext<P>, rng: &mut R) -> Result<SharedSecret, Error> {
+    decaps_with::<P, _>(dk, c, |s, c, m| kpke_dec_masked::<P, R>(s, c, m, rng))
+}
+
+// Algorithm 18 with the shuffled K-PKE.Decrypt, see kpke_dec_shuffled
+pub(crate) fn decaps_shuffled_internal<P: ParameterSet, R: RngCore + CryptoRng>(dk: &DecapsulationKey<P>, c: &Ciphertext<P>, rng: &mut R) -> Result<SharedSecret, Error> {
+    decaps_with::<P, _>(dk, c, |s, c, m| kpke_dec_shuffled::<P, R>(s, c, m, rng))
+}
+
+// Algorithm 18 hardened against fault injection, which could otherwise skip the check c == c' and turn
+// decapsulation into a plaintext-checking oracle. dk is checked against its stored H(ek) before use,
+// the comparison and the selection are each computed twice, independently, and any inconsistency yields K̄.
+pub(crate) fn decaps_hardened_internal<P: ParameterSet>(dk: &DecapsulationKey<P>, c: &Ciphertext<P>) -> Result<SharedSecret, Error> {
+    decaps_hardened_bytes::<P>(dk.as_bytes(), dk.s_hat(), c.as_bytes())
+}
+
+fn decaps_hardened_bytes<P: ParameterSet>(dk: &[u8], s_hat: &P::PolyVec, c: &[u8]) -> Result<SharedSecret, Error> {
+    if dk.len() != P::DK_LEN || c.len() != P::CT_LEN {
+        return Err(Error::InvalidLength);
+    }
+    let k = P::K;
+    let dk_ok = ct_eq(&h(&dk[384*k..768*k+32]), &dk[768*k+32..768*k+64]);
+    let (k_, kk, c_) = decaps_candidates::<P, _>(dk, c, |c, m| kpke_dec_expanded::<P>(s_hat, c, m))?;
+
+    let accept1 = dk_ok & ct_eq(black_box(c), c_.as_ref());
+    let accept2 = black_box(dk_ok) & ct_eq(black_box(c_.as_ref()), c);
+    let mut k = Zeroizing::new([0u8; 32]);
+    select_key_twice(&k_, &kk, accept1, accept2, &mut k);
+    Ok(SharedSecret::from_bytes(*k))
+}
+
+// K' if both accept masks are set, otherwise K̄. k1 and k2 are selected independently and compared,
+// so a fault in either selection or its mask falls back to K̄.
+fn select_key_twice(k_: &[u8; 32], kk: &[u8; 32], accept1: u8, accept2: u8, k: &mut [u8; 32]) {
+    let mut k1 = Zeroizing::new(*kk);
+    ct_select(&mut *k1, k_, accept1);
+    let mut k2 = Zeroizing::new(*kk);
+    ct_select(&mut *k2, black_box(k_), accept2);
+    k.copy_from_slice(kk);
+    ct_select(k, &*k1, ct_eq(&*k1, black_box(&*k2)));
+}
+
+fn decaps_with<P, F>(dk: &DecapsulationKey<P>, c: &Ciphertext<P>, decrypt: F) -> Result<SharedSecret, Error>
+where
+    P: ParameterSet,
+    F: FnOnce(&P::PolyVec, &[u8], &mut [u8]) -> Result<(), Error>,
+{
+    let mut k = Zeroizing::new([0u8; 32]);
+    decaps_into_with::<P, F>(dk, c, &mut k, decrypt)?;
+    Ok(SharedSecret::from_bytes(*k))
+}
+
+fn decaps_into_with<P, F>(dk: &DecapsulationKey<P>, c: &Ciphertext<P>, k: &mut [u8; 32], decrypt: F) -> Result<(), Error>
+where
+    P: ParameterSet,
+    F: FnOnce(&P::PolyVec, &[u8], &mut [u8]) -> Result<(), Error>,
+{
+    let c = c.as_bytes();
+    let candidates = decaps_candidates::<P, _>(dk.as_bytes(), c, |c, m| decrypt(dk.s_hat(), c, m))?;
+    select_key::<P>(c, candidates, k);
+    Ok(())
+}
+
+// K' if c == c', otherwise the implicit rejection key K̄
+fn select_key<P: ParameterSet>(c: &[u8], (k_, kk, c_): Candidates<P>, k: &mut [u8; 32]) {
+    k.copy_from_slice(&*kk);
+    let mask = ct_eq(c, c_.as_ref());
+    ct_select(k, &*k_, mask);
+}
+
+// K', the implicit rejection key K̄ and the re-encryption c'
+type Candidates<P> = (Zeroizing<[u8; 32]>, Zeroizing<[u8; 32]>, <P as ParameterSet>::CiphertextBytes);
+
+// Decrypts c with the given K-PKE.Decrypt and returns K', K̄ and c'
+fn decaps_candidates<P, F>(dk: &[u8], c: &[u8], decrypt: F) -> Result<Candidates<P>, Error>
+where
+    P: ParameterSet,
+    F: FnOnce(&[u8], &mut [u8]) -> Result<(), Error>,
+{
+    let k = P::K;
+    let ek_pke = &dk[384*k..768*k+32];
+    let h = &dk[768*k+32..768*k+64];
+    let z = &dk[768*k+64..768*k+96];
+    decaps_candidates_with::<P, _, _>(h, z, c, decrypt, |m, r, c_| kpke_enc::<P>(ek_pke, m, r, c_))
+}
+
+// Decrypts c and re-encrypts with the given K-PKE operations, returning K', K̄ and c'
+fn decaps_candidates_with<P, D, E>(h: &[u8], z: &[u8], c: &[u8], decrypt: D, encrypt: E) -> Result<Candidates<P>, Error>
+where
+    P: ParameterSet,
+    D: FnOnce(&[u8], &mut [u8]) -> Result<(), Error>,
+    E: FnOnce(&[u8], &[u8], &mut [u8]) -> Result<(), Error>,
+{
+    let mut m_ = Zeroizing::new([0u8; 64]);
+    decrypt(c, &mut m_[..32])?;
+    m_[32..].copy_from_slice(h);
+    let (k_, r_) = g(&*m_);
+    let (k_, r_) = (Zeroizing::new(k_), Zeroizing::new(r_));
+    // z || c, sized for the largest ciphertext
+    let mut z_ = [0u8; 32 + 1568];
+    z_[..32].copy_from_slice(z);
+    z_[32..32 + c.len()].copy_from_slice(c);
+    let kk = Zeroizing::new(j(&z_[..32 + c.len()]));
+    z_[..32].zeroize();
+
+    let mut c_ = P::CiphertextBytes::zeroed();
+    encrypt(&m_[..32], &*r_, c_.as_mut())?;
+    Ok((k_, kk, c_))
 }
 
 // Algorithm 19: Generates an encapsulation key and a corresponding decapsulation key.
-pub fn keygen() -> (Vec<u16>, Vec<u16>) {
-    let d = random_bytes(32);
-    let z = random_bytes(32).iter().map(|x| *x as u16).collect();
-    keygen_internal(d, z)
+#[cfg(feature = "std")]
+pub fn keygen<P: ParameterSet>() -> Result<(EncapsulationKey<P>, DecapsulationKey<P>), Error> {
+    keygen_with_rng::<P, _>(&mut rand::thread_rng())
+}
+
+pub fn keygen_with_rng<P: ParameterSet, R: RngCore + CryptoRng>(rng: &mut R) -> Result<(EncapsulationKey<P>, DecapsulationKey<P>), Error> {
+    ensure_self_test()?;
+    let mut d = [0u8; 32];
+    let mut z = [0u8; 32];
+    let key_pair = random_bytes_with_rng(rng, &mut d)
+        .and_then(|_| random_bytes_with_rng(rng, &mut z))
+        .and_then(|_| keygen_internal::<P>(d, z));
+    d.zeroize();
+    z.zeroize();
+    #[cfg(feature = "pct")]
+    if let Ok((ek, dk)) = &key_pair {
+        pairwise_consistency_test(ek, dk, rng)?;
+    }
+    key_pair
+}
+
+// Pairwise consistency test: encapsulate to ek, decapsulate with dk and compare the shared secrets
+pub fn pairwise_consistency_test<P: ParameterSet, R: RngCore + CryptoRng>(ek: &EncapsulationKey<P>, dk: &DecapsulationKey<P>, rng: &mut R) -> Result<(), Error> {
+    let (k, c) = encaps_with_rng(ek, rng)?;
+    let k_ = decaps(dk, &c)?;
+    if ct_eq(k.as_bytes(), k_.as_bytes()) != 0xff {
+        return Err(Error::PairwiseConsistency);
+    }
+    Ok(())
 }
 
 // Algorithm 20: Uses the encapsulation key to generate a shared secret key and an associated ciphertext.
-pub fn encaps(ek: Vec<u16>) -> (Vec<u8>, Vec<u16>) {
-    let m = random_bytes(32);
-    encaps_internal(ek, m)
+#[cfg(feature = "std")]
+pub fn encaps<P: ParameterSet>(ek: &EncapsulationKey<P>) -> Result<(SharedSecret, Ciphertext<P>), Error> {
+    encaps_with_rng::<P, _>(ek, &mut rand::thread_rng())
+}
+
+pub fn encaps_with_rng<P: ParameterSet, R: RngCore + CryptoRng>(ek: &EncapsulationKey<P>, rng: &mut R) -> Result<(SharedSecret, Ciphertext<P>), Error> {
+    ensure_self_test()?;
+    let mut m = [0u8; 32];
+    let result = random_bytes_with_rng(rng, &mut m).and_then(|_| encaps_internal::<P>(ek, m));
+    m.zeroize();
+    result
+}
+
+// Algorithm 20 writing the ciphertext and the shared secret key into caller-provided buffers
+#[cfg(feature = "std")]
+pub fn encapsulate_into<P: ParameterSet>(ek: &EncapsulationKey<P>, c: &mut P::CiphertextBytes, k: &mut [u8; 32]) -> Result<(), Error> {
+    encapsulate_into_with_rng::<P, _>(ek, c, k, &mut rand::thread_rng())
+}
+
+pub fn encapsulate_into_with_rng<P: ParameterSet, R: RngCore + CryptoRng>(ek: &EncapsulationKey<P>, c: &mut P::CiphertextBytes, k: &mut [u8; 32], rng: &mut R) -> Result<(), Error> {
+    ensure_self_test()?;
+    let mut m = [0u8; 32];
+    let result = random_bytes_with_rng(rng, &mut m).and_then(|_| encaps_into_internal::<P>(ek, &m, c, k));
+    m.zeroize();
+    result
+}
+
+// Deterministic encapsulation with caller-supplied randomness m (ML-KEM.Encaps_internal), wiped before returning
+pub fn encapsulate_deterministic<P: ParameterSet>(ek: &EncapsulationKey<P>, mut m: [u8; 32]) -> Result<(SharedSecret, Ciphertext<P>), Error> {
+    let result = ensure_self_test().and_then(|_| encaps_internal::<P>(ek, m));
+    m.zeroize();
+    result
 }
 
 // Algorithm 21: Uses the decapsulation key to produce a shared secret key from a ciphertext.
-pub fn decaps(dk: Vec<u16>, c: Vec<u16>) -> Vec<u8> {
-    decaps_internal(dk, c)
+pub fn decaps<P: ParameterSet>(dk: &DecapsulationKey<P>, c: &Ciphertext<P>) -> Result<SharedSecret, Error> {
+    ensure_self_test()?;
+    decaps_internal::<P>(dk, c)
+}
+
+// Algorithm 21 writing the shared secret key into a caller-provided buffer
+pub fn decapsulate_into<P: ParameterSet>(dk: &DecapsulationKey<P>, c: &Ciphertext<P>, k: &mut [u8; 32]) -> Result<(), Error> {
+    ensure_self_test()?;
+    decaps_into_with::<P, _>(dk, c, k, kpke_dec_expanded::<P>)
+}
+
+// Algorithm 21 with a decapsulation key expanded once by DecapsulationKey::expand
+pub fn decaps_expanded<P: ParameterSet>(dk: &ExpandedDecapsulationKey<P>, c: &Ciphertext<P>) -> Result<SharedSecret, Error> {
+    ensure_self_test()?;
+    decaps_expanded_internal::<P>(dk, c)
+}
+
+// Algorithm 21 with first-order masking of the secret vector s, drawing fresh masks for every call
+#[cfg(feature = "std")]
+pub fn decaps_masked<P: ParameterSet>(dk: &DecapsulationKey<P>, c: &Ciphertext<P>) -> Result<SharedSecret, Error> {
+    decaps_masked_with_rng::<P, _>(dk, c, &mut rand::thread_rng())
+}
+
+pub fn decaps_masked_with_rng<P: ParameterSet, R: RngCore + CryptoRng>(dk: &DecapsulationKey<P>, c: &Ciphertext<P>, rng: &mut R) -> Result<SharedSecret, Error> {
+    ensure_self_test()?;
+    decaps_masked_internal::<P, R>(dk, c, rng)
+}
+
+// Algorithm 21 with the fault-attack hardening of decaps_hardened_internal
+pub fn decaps_hardened<P: ParameterSet>(dk: &DecapsulationKey<P>, c: &Ciphertext<P>) -> Result<SharedSecret, Error> {
+    ensure_self_test()?;
+    decaps_hardened_internal::<P>(dk, c)
+}
+
+// Algorithm 21 with the NTTs and base case multiplications of decryption in a random order for every call
+#[cfg(feature = "std")]
+pub fn decaps_shuffled<P: ParameterSet>(dk: &DecapsulationKey<P>, c: &Ciphertext<P>) -> Result<SharedSecret, Error> {
+    decaps_shuffled_with_rng::<P, _>(dk, c, &mut rand::thread_rng())
+}
+
+pub fn decaps_shuffled_with_rng<P: ParameterSet, R: RngCore + CryptoRng>(dk: &DecapsulationKey<P>, c: &Ciphertext<P>, rng: &mut R) -> Result<SharedSecret, Error> {
+    ensure_self_test()?;
+    decaps_shuffled_internal::<P, R>(dk, c, rng)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::params::{MlKem512, MlKem768, MlKem1024};
+    use crate::types::KeyPair;
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::vec;
+    use std::vec::Vec;
+
+    fn keygen_encaps_decaps<P: ParameterSet>(seed: u8) {
+        let (ek, dk) = KeyPair::<P>::from_seed([seed; 32], [seed ^ 0xff; 32]).unwrap().into_parts();
+        let (k, c) = encapsulate_deterministic::<P>(&ek, [seed ^ 0x0f; 32]).unwrap();
+        assert_eq!(encapsulate_deterministic::<P>(&ek, [seed ^ 0x0f; 32]).unwrap(), (k.clone(), c.clone()));
+        let k_ = decaps::<P>(&dk, &c).unwrap();
+        assert_eq!(k, k_);
+    }
+
+    #[cfg(feature = "std")]
+    fn random_keygen_encaps_decaps<P: ParameterSet>() {
+        let (ek, dk) = keygen::<P>().unwrap();
+        let (k, c) = encaps::<P>(&ek).unwrap();
+        let k_ = decaps::<P>(&dk, &c).unwrap();
+        assert_eq!(k, k_);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_random_keygen_encaps_decaps() {
+        random_keygen_encaps_decaps::<MlKem512>();
+        random_keygen_encaps_decaps::<MlKem768>();
+        random_keygen_encaps_decaps::<MlKem1024>();
+    }
 
     #[test]
     fn test_keygen_encaps_decaps() {
-        let (ek, dk) = keygen();
-        let (k, c) = encaps(ek);
-        let k_ = decaps(dk, c);
-        assert_eq!(k, k_);
+        keygen_encaps_decaps::<MlKem512>(2);
+        keygen_encaps_decaps::<MlKem768>(2);
+        keygen_encaps_decaps::<MlKem1024>(2);
+    }
+
+    #[test]
+    fn test_validate_encapsulation_key() {
+        let (ek, _) = keygen_internal::<MlKem512>([2; 32], [3; 32]).unwrap();
+        assert_eq!(validate_encapsulation_key::<MlKem512>(ek.as_bytes()), Ok(()));
+        assert_eq!(validate_encapsulation_key::<MlKem512>(&ek.as_bytes()[1..]), Err(Error::InvalidLength));
+
+        // First coefficient of the second polynomial set to 0xfff >= q
+        let mut bytes = ek.as_bytes().to_vec();
+        bytes[384] = 0xff;
+        bytes[385] |= 0x0f;
+        assert_eq!(validate_encapsulation_key::<MlKem512>(&bytes), Err(Error::ModulusCheck));
+        let ek = EncapsulationKey::<MlKem512>::from_bytes(&bytes).unwrap();
+        assert_eq!(encapsulate_deterministic(&ek, [0; 32]), Err(Error::ModulusCheck));
+    }
+
+    #[test]
+    fn test_validate_decapsulation_key() {
+        let (_, dk) = keygen_internal::<MlKem512>([2; 32], [3; 32]).unwrap();
+        assert_eq!(validate_decapsulation_key::<MlKem512>(dk.as_bytes()), Ok(()));
+        assert_eq!(validate_decapsulation_key::<MlKem512>(&dk.as_bytes()[..1600]), Err(Error::InvalidLength));
+
+        let mut bytes = dk.as_bytes().to_vec();
+        bytes[768] ^= 1;
+        assert_eq!(validate_decapsulation_key::<MlKem512>(&bytes), Err(Error::HashCheck));
+        assert_eq!(DecapsulationKey::<MlKem512>::from_bytes(&bytes), Err(Error::HashCheck));
+    }
+
+    #[test]
+    fn test_validate_ciphertext() {
+        assert_eq!(validate_ciphertext::<MlKem512>(&[0; 768]), Ok(()));
+        assert_eq!(validate_ciphertext::<MlKem512>(&[0; 1088]), Err(Error::InvalidLength));
+    }
+
+    #[test]
+    fn test_decaps_implicit_rejection() {
+        let (ek, dk) = keygen_internal::<MlKem768>([2; 32], [3; 32]).unwrap();
+        let (k, c) = encaps_internal(&ek, [4; 32]).unwrap();
+        let mut bytes = c.as_bytes().to_vec();
+        bytes[0] ^= 1;
+        let c_bad = Ciphertext::<MlKem768>::from_bytes(&bytes).unwrap();
+        assert_eq!(decaps(&dk, &c).unwrap(), k);
+
+        // K̄ = J(z || c)
+        let mut z_c = dk.as_bytes()[2368..].to_vec();
+        z_c.extend_from_slice(c_bad.as_bytes());
+        assert_eq!(decaps(&dk, &c_bad).unwrap().as_bytes(), &j(&z_c));
+    }
+
+    // Welch's t-statistic between the running times of f(false) and f(true), measured as in dudect:
+    // the two classes are interleaved at random and the slowest 5% of each class are cropped
+    fn timing_t_statistic<F: FnMut(bool)>(mut f: F, samples: usize) -> f64 {
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut times = [Vec::with_capacity(samples), Vec::with_capacity(samples)];
+        for _ in 0..2 * samples {
+            let class = rng.next_u32() & 1 == 1;
+            let start = std::time::Instant::now();
+            f(class);
+            times[class as usize].push(start.elapsed().as_nanos() as f64);
+        }
+        let [a, b] = times.map(|mut t| {
+            t.sort_by(|x, y| x.total_cmp(y));
+            t.truncate(t.len() * 95 / 100);
+            let n = t.len() as f64;
+            let mean = t.iter().sum::<f64>() / n;
+            let variance = t.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / (n - 1.0);
+            (mean, variance / n)
+        });
+        (a.0 - b.0) / (a.1 + b.1).sqrt()
+    }
+
+    // Checks that decapsulation takes as long for a rejected ciphertext as for a valid one.
+    // |t| > 10 is dudect's threshold for a clear leak. Timings depend on the machine and
+    // the build, so this is ignored by default and run by the timing job in .github/workflows/ci.yml:
+    // cargo test --release --lib -- --ignored
+    #[test]
+    #[ignore]
+    fn test_decaps_timing_valid_vs_rejected() {
+        let (ek, dk) = keygen_internal::<MlKem768>([2; 32], [3; 32]).unwrap();
+        let (_, c) = encaps_internal(&ek, [4; 32]).unwrap();
+        let mut bytes = c.as_bytes().to_vec();
+        bytes[0] ^= 1;
+        let c_bad = Ciphertext::<MlKem768>::from_bytes(&bytes).unwrap();
+        for decapsulate in [decaps::<MlKem768>, decaps_hardened::<MlKem768>] {
+            let t = timing_t_statistic(|rejected| {
+                black_box(decapsulate(&dk, if rejected { &c_bad } else { &c }).unwrap());
+            }, 20000);
+            assert!(t.abs() < 10.0, "t = {}", t);
+        }
+    }
+
+    #[test]
+    fn test_decaps_hardened() {
+        let (ek, dk) = keygen_internal::<MlKem768>([2; 32], [3; 32]).unwrap();
+        let (k, c) = encaps_internal(&ek, [4; 32]).unwrap();
+        let mut bytes = c.as_bytes().to_vec();
+        bytes[0] ^= 1;
+        let c_bad = Ciphertext::<MlKem768>::from_bytes(&bytes).unwrap();
+        assert_eq!(decaps_hardened(&dk, &c).unwrap(), k);
+        assert_eq!(decaps_hardened(&dk, &c_bad).unwrap(), decaps(&dk, &c_bad).unwrap());
+
+        // A dk whose ek no longer matches H(ek) gives K̄ = J(z || c), even for a valid ciphertext
+        let mut dk_bytes = dk.as_bytes().to_vec();
+        dk_bytes[1152] ^= 1;
+        let mut z_c = dk_bytes[2368..].to_vec();
+        z_c.extend_from_slice(c.as_bytes());
+        let k_bar = decaps_hardened_bytes::<MlKem768>(&dk_bytes, dk.s_hat(), c.as_bytes()).unwrap();
+        assert_eq!(k_bar.as_bytes()[..], j(&z_c)[..]);
+        assert_eq!(decaps_hardened_bytes::<MlKem768>(&dk_bytes[..2399], dk.s_hat(), c.as_bytes()), Err(Error::InvalidLength));
+    }
+
+    #[test]
+    fn test_select_key_twice() {
+        let mut k = [0u8; 32];
+        for (accept1, accept2, expected) in [(0xff, 0xff, [1; 32]), (0, 0, [2; 32]), (0xff, 0, [2; 32]), (0, 0xff, [2; 32])] {
+            select_key_twice(&[1; 32], &[2; 32], accept1, accept2, &mut k);
+            assert_eq!(k, expected);
+        }
+    }
+
+    type Decapsulate<P> = fn(&DecapsulationKey<P>, &Ciphertext<P>, &mut StdRng) -> Result<SharedSecret, Error>;
+
+    // Every decapsulation variant of the public API, the randomized ones drawing from the given RNG
+    fn decapsulators<P: ParameterSet>() -> Vec<(&'static str, Decapsulate<P>)> {
+        #[cfg_attr(not(feature = "std"), allow(unused_mut))]
+        let mut decapsulators: Vec<(&'static str, Decapsulate<P>)> = vec![
+            ("decaps", |dk, c, _| decaps(dk, c)),
+            ("decapsulate_into", |dk, c, _| {
+                let mut k = [0xff; 32];
+                decapsulate_into(dk, c, &mut k).map(|_| SharedSecret::from_bytes(k))
+            }),
+            ("decaps_expanded", |dk, c, _| decaps_expanded(&dk.expand()?, c)),
+            ("decaps_masked_with_rng", |dk, c, rng| decaps_masked_with_rng(dk, c, rng)),
+            ("decaps_shuffled_with_rng", |dk, c, rng| decaps_shuffled_with_rng(dk, c, rng)),
+            ("decaps_hardened", |dk, c, _| decaps_hardened(dk, c)),
+        ];
+        #[cfg(feature = "std")]
+        decapsulators.extend([
+            ("decaps_masked", (|dk, c, _| decaps_masked(dk, c)) as Decapsulate<P>),
+            ("decaps_shuffled", |dk, c, _| decaps_shuffled(dk, c)),
+        ]);
+        decapsulators
+    }
+
+    // Each variant gives K for a valid ciphertext and the same K̄ as decaps for a modified one
+    fn decaps_variants<P: ParameterSet>(seed: u64) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let (ek, dk) = keygen_with_rng::<P, _>(&mut rng).unwrap();
+        let (k, c) = encaps_with_rng(&ek, &mut rng).unwrap();
+        let mut bytes = c.as_bytes().to_vec();
+        bytes[0] ^= 1;
+        let c_bad = Ciphertext::<P>::from_bytes(&bytes).unwrap();
+        let k_bar = decaps(&dk, &c_bad).unwrap();
+        for (name, decapsulate) in decapsulators::<P>() {
+            assert_eq!(decapsulate(&dk, &c, &mut rng).unwrap(), k, "{}", name);
+            assert_eq!(decapsulate(&dk, &c_bad, &mut rng).unwrap(), k_bar, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_decaps_variants() {
+        decaps_variants::<MlKem512>(2);
+        decaps_variants::<MlKem768>(3);
+        decaps_variants::<MlKem1024>(4);
+    }
+
+    #[test]
+    fn test_encapsulate_into() {
+        let ek = keygen_with_rng::<MlKem768, _>(&mut StdRng::seed_from_u64(2)).unwrap().0;
+        let (k, c) = encaps_with_rng(&ek, &mut StdRng::seed_from_u64(3)).unwrap();
+        let mut c_ = [0u8; 1088];
+        let mut k_ = [0u8; 32];
+        encapsulate_into_with_rng(&ek, &mut c_, &mut k_, &mut StdRng::seed_from_u64(3)).unwrap();
+        assert_eq!((&c_[..], &k_), (c.as_bytes(), k.as_bytes()));
+        #[cfg(feature = "std")]
+        {
+            let (_, dk) = keygen_with_rng::<MlKem768, _>(&mut StdRng::seed_from_u64(2)).unwrap();
+            encapsulate_into(&ek, &mut c_, &mut k_).unwrap();
+            assert_eq!(decaps(&dk, &Ciphertext::from_bytes(&c_).unwrap()).unwrap().as_bytes(), &k_);
+        }
+    }
+
+    #[test]
+    fn test_pairwise_consistency() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let (ek, dk) = keygen_internal::<MlKem512>([2; 32], [3; 32]).unwrap();
+        let (ek_, dk_) = keygen_internal::<MlKem512>([4; 32], [3; 32]).unwrap();
+        assert_eq!(pairwise_consistency_test(&ek, &dk, &mut rng), Ok(()));
+        assert_eq!(pairwise_consistency_test(&ek_, &dk_, &mut rng), Ok(()));
+        assert_eq!(pairwise_consistency_test(&ek, &dk_, &mut rng), Err(Error::PairwiseConsistency));
+        assert_eq!(pairwise_consistency_test(&ek_, &dk, &mut rng), Err(Error::PairwiseConsistency));
+    }
+
+    #[test]
+    fn test_with_rng() {
+        let (ek, dk) = keygen_with_rng::<MlKem768, _>(&mut StdRng::seed_from_u64(2)).unwrap();
+        let (ek_, dk_) = keygen_with_rng::<MlKem768, _>(&mut StdRng::seed_from_u64(2)).unwrap();
+        assert_eq!((&ek, &dk), (&ek_, &dk_));
+
+        let (k, c) = encaps_with_rng(&ek, &mut StdRng::seed_from_u64(2)).unwrap();
+        let (k_, c_) = encaps_with_rng(&ek, &mut StdRng::seed_from_u64(2)).unwrap();
+        assert_eq!((&k, &c), (&k_, &c_));
+        assert_eq!(decaps(&dk, &c).unwrap(), k);
     }
 }
\ No newline at end of file
@@ -0,0 +1,137 @@
+use core::fmt::Debug;
+use zeroize::Zeroize;
+use crate::matrix::{FixedArray, Matrix};
+use crate::poly::Polynomial;
+
+// Fixed-size byte storage backing the typed keys and ciphertexts
+pub trait ByteArray: AsRef<[u8]> + AsMut<[u8]> + Clone + PartialEq + Eq + Debug + Zeroize {
+    fn zeroed() -> Self;
+}
+
+impl<const L: usize> ByteArray for [u8; L] {
+    fn zeroed() -> Self {
+        [0u8; L]
+    }
+}
+
+// Parameter sets from Section 8 - Parameter Sets
+pub trait ParameterSet: Debug + Clone + Copy + PartialEq + Eq {
+    const NAME: &'static str;
+
+    const K: usize;
+
+    const ETA1: usize;
+
+    const ETA2: usize;
+
+    const DU: usize;
+
+    const DV: usize;
+
+    // Sizes (in bytes) of keys and ciphertext, Table 3
+    const EK_LEN: usize = 384 * Self::K + 32;
+
+    const DK_LEN: usize = 768 * Self::K + 96;
+
+    const CT_LEN: usize = 32 * (Self::DU * Self::K + Self::DV);
+
+    type EncapsulationKeyBytes: ByteArray;
+
+    type DecapsulationKeyBytes: ByteArray;
+
+    type CiphertextBytes: ByteArray;
+
+    // Vectors and matrices of K polynomials, stored inline without a heap allocation
+    type PolyVec: FixedArray<Polynomial> + Zeroize;
+
+    type PolyMatrix: FixedArray<Self::PolyVec>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MlKem512;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MlKem768;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MlKem1024;
+
+impl ParameterSet for MlKem512 {
+    const NAME: &'static str = "ML-KEM-512";
+    const K: usize = 2;
+    const ETA1: usize = 3;
+    const ETA2: usize = 2;
+    const DU: usize = 10;
+    const DV: usize = 4;
+    type EncapsulationKeyBytes = [u8; 800];
+    type DecapsulationKeyBytes = [u8; 1632];
+    type CiphertextBytes = [u8; 768];
+    type PolyVec = [Polynomial; 2];
+    type PolyMatrix = Matrix<2>;
+}
+
+impl ParameterSet for MlKem768 {
+    const NAME: &'static str = "ML-KEM-768";
+    const K: usize = 3;
+    const ETA1: usize = 2;
+    const ETA2: usize = 2;
+    const DU: usize = 10;
+    const DV: usize = 4;
+    type EncapsulationKeyBytes = [u8; 1184];
+    type DecapsulationKeyBytes = [u8; 2400];
+    type CiphertextBytes = [u8; 1088];
+    type PolyVec = [Polynomial; 3];
+    type PolyMatrix = Matrix<3>;
+}
+
+impl ParameterSet for MlKem1024 {
+    const NAME: &'static str = "ML-KEM-1024";
+    const K: usize = 4;
+    const ETA1: usize = 2;
+    const ETA2: usize = 2;
+    const DU: usize = 11;
+    const DV: usize = 5;
+    type EncapsulationKeyBytes = [u8; 1568];
+    type DecapsulationKeyBytes = [u8; 3168];
+    type CiphertextBytes = [u8; 1568];
+    type PolyVec = [Polynomial; 4];
+    type PolyMatrix = Matrix<4>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sizes() {
+        assert_eq!((MlKem512::EK_LEN, MlKem512::DK_LEN, MlKem512::CT_LEN), (800, 1632, 768));
+        assert_eq!((MlKem768::EK_LEN, MlKem768::DK_LEN, MlKem768::CT_LEN), (1184, 2400, 1088));
+        assert_eq!((MlKem1024::EK_LEN, MlKem1024::DK_LEN, MlKem1024::CT_LEN), (1568, 3168, 1568));
+    }
+
+    fn byte_array_lengths<P: ParameterSet>() {
+        assert_eq!(P::EncapsulationKeyBytes::zeroed().as_ref().len(), P::EK_LEN);
+        assert_eq!(P::DecapsulationKeyBytes::zeroed().as_ref().len(), P::DK_LEN);
+        assert_eq!(P::CiphertextBytes::zeroed().as_ref().len(), P::CT_LEN);
+    }
+
+    #[test]
+    fn test_byte_array_lengths() {
+        byte_array_lengths::<MlKem512>();
+        byte_array_lengths::<MlKem768>();
+        byte_array_lengths::<MlKem1024>();
+    }
+
+    fn poly_array_lengths<P: ParameterSet>() {
+        let a = P::PolyMatrix::from_fn(|_| P::PolyVec::from_fn(|_| Polynomial::zero_polynomial()));
+        assert_eq!(a.as_ref().len(), P::K);
+        assert!(a.as_ref().iter().all(|row| row.as_ref().len() == P::K));
+    }
+
+    #[test]
+    fn test_poly_array_lengths() {
+        poly_array_lengths::<MlKem512>();
+        poly_array_lengths::<MlKem768>();
+        poly_array_lengths::<MlKem1024>();
+    }
+}
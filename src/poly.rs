@@ -1,44 +1,82 @@
-use std::ops::{Add, Mul, Sub};
-use crate::encode::bytes_to_bits;
+use core::ops::{Add, Mul, Sub};
 use crate::field::FieldElement as FF;
-use crate::helper::{xof, base_case_multiply};
+use crate::helper::{Xof, base_case_multiply};
 use crate::constant::{CONST1, CONST2, Q, N};
+use crate::error::Error;
+use zeroize::Zeroize;
+use core::fmt;
+use crate::debug::DebugSecret;
+use crate::shuffle::Permutation;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Clone, PartialEq)]
 pub struct Polynomial {
-    pub coeffs: Vec<FF>,
+    pub coeffs: [FF; N],
 }
 
 impl Polynomial{
     pub const N: usize = N;
     pub const G: FF = FF(17);
-    pub fn new(mut coeffs: Vec<FF>) -> Polynomial {
-        coeffs = Polynomial::padding_zeros(coeffs);
+    pub fn new(coeffs: [FF; N]) -> Polynomial {
         Polynomial { coeffs }
     }
 
-    pub fn padding_zeros(coeffs: Vec<FF>) -> Vec<FF> {
-        let mut padded_coeffs = coeffs.clone();
-        while padded_coeffs.len() < Polynomial::N {
-            padded_coeffs.push(FF(0));
-        }
-        padded_coeffs
+    // The first coeffs.len() coefficients, padded with zeros
+    pub fn from_slice(coeffs: &[FF]) -> Polynomial {
+        let mut f = Polynomial::zero_polynomial();
+        f.coeffs[..coeffs.len()].copy_from_slice(coeffs);
+        f
     }
 
     pub fn zero_polynomial() -> Polynomial {
-        Polynomial::new(vec![FF(0); Polynomial::N])
+        Polynomial::new([FF(0); Polynomial::N])
+    }
+
+    // Coefficients as integers in [0, q), and back
+    pub fn list(&self) -> [u16; N] {
+        self.coeffs.map(|x| x.to_int())
+    }
+
+    pub fn from_list(list: &[u16; N]) -> Polynomial {
+        Polynomial::new(list.map(FF))
+    }
+}
+
+impl fmt::Debug for Polynomial {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Polynomial").finish_non_exhaustive()
+    }
+}
+
+impl DebugSecret for Polynomial {
+    fn fmt_secret(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Polynomial").field("coeffs", &self.list()).finish()
+    }
+}
+
+// Vectors of polynomials, such as ParameterSet::PolyVec
+impl DebugSecret for [Polynomial] {
+    fn fmt_secret(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter().map(|p| p.unredacted())).finish()
     }
+}
+
+impl Zeroize for Polynomial {
+    fn zeroize(&mut self) {
+        self.coeffs.zeroize();
+    }
+}
 
-    pub fn list(&self) -> Vec<u16> {
-        let coeffs = self.coeffs.clone();
-        coeffs.iter().map(|x| x.to_int()).collect::<Vec<_>>().to_vec()
+// Coefficients may be secret (s, e, y, m), so every polynomial is wiped when dropped
+impl Drop for Polynomial {
+    fn drop(&mut self) {
+        self.coeffs.zeroize();
     }
 }
 
 // Algorithm 9: Computes ̂ the NTT representation 𝑓 of the given polynomial 𝑓 ∈ 𝑅𝑞.
 impl Polynomial {
     pub fn ntt(self) -> Polynomial {
-        let mut f_ntt = self.clone();
+        let mut f_ntt = self;
         let mut i: usize = 1;
         for len in [128, 64, 32, 16, 8, 4, 2] {
             for start in (0..N).step_by(2 * len) {
@@ -53,12 +91,32 @@ impl Polynomial {
         }
         f_ntt
     }
+
+    // Algorithm 9 with the butterflies of each layer in the order given by perm.
+    // Butterfly b of a layer belongs to block b / len, whose zeta is CONST1[128 / len + b / len].
+    // b comes from the secret permutation, and len is a power of two, so b / len and b % len are a shift
+    // and a mask rather than a variable-time division.
+    pub fn ntt_shuffled(self, perm: &Permutation) -> Polynomial {
+        let mut f_ntt = self;
+        for len in [128usize, 64, 32, 16, 8, 4, 2] {
+            let shift = len.trailing_zeros();
+            for b in perm.iter() {
+                let block = b >> shift;
+                let zeta = FF::new(CONST1[(128 >> shift) + block]);
+                let j = 2 * len * block + (b & (len - 1));
+                let t = zeta * f_ntt.coeffs[j + len];
+                f_ntt.coeffs[j + len] = f_ntt.coeffs[j] - t;
+                f_ntt.coeffs[j] = f_ntt.coeffs[j] + t;
+            }
+        }
+        f_ntt
+    }
 }
 
 // Algorithm 10: Computes ̂the polynomial 𝑓 ∈ 𝑅𝑞 that corresponds to the given NTT representation 𝑓 ∈ 𝑇𝑞.
 impl Polynomial {
     pub fn intt(self) -> Polynomial {
-        let mut f_intt = self.clone();
+        let mut f_intt = self;
         let mut i: usize = 127;
         for len in [2, 4, 8, 16, 32, 64, 128] {
             for start in (0..N).step_by(2 * len) {
@@ -76,13 +134,47 @@ impl Polynomial {
         }
         f_intt
     }
+
+    // Algorithm 10 with the butterflies of each layer, and the final scaling, in the order given by perm.
+    // Butterfly b of a layer belongs to block b / len, whose zeta is CONST1[256 / len - 1 - b / len],
+    // with b / len and b % len computed as in ntt_shuffled.
+    pub fn intt_shuffled(self, perm: &Permutation) -> Polynomial {
+        let mut f_intt = self;
+        for len in [2usize, 4, 8, 16, 32, 64, 128] {
+            let shift = len.trailing_zeros();
+            for b in perm.iter() {
+                let block = b >> shift;
+                let zeta = FF::new(CONST1[(256 >> shift) - 1 - block]);
+                let j = 2 * len * block + (b & (len - 1));
+                let t = f_intt.coeffs[j];
+                f_intt.coeffs[j] = t + f_intt.coeffs[j + len];
+                f_intt.coeffs[j + len] = zeta * (f_intt.coeffs[j + len] - t);
+            }
+        }
+        for i in perm.iter() {
+            f_intt.coeffs[2 * i] = f_intt.coeffs[2 * i] * FF(3303);
+            f_intt.coeffs[2 * i + 1] = f_intt.coeffs[2 * i + 1] * FF(3303);
+        }
+        f_intt
+    }
 }
 
 // Algorithm 11: Computes the product (in the ring 𝑇𝑞) of two NTT representations.
 impl Polynomial {
-    pub fn multiply_ntt(f: Polynomial, g: Polynomial) -> Polynomial {
+    pub fn multiply_ntt(f: &Polynomial, g: &Polynomial) -> Polynomial {
+        let mut h = Polynomial::zero_polynomial();
+        for (i, &gamma) in CONST2.iter().enumerate() {
+            let coeffs = base_case_multiply(f.coeffs[2 * i], f.coeffs[2 * i + 1], g.coeffs[2 * i], g.coeffs[2 * i + 1], FF::new(gamma));
+            h.coeffs[2 * i] = coeffs.0;
+            h.coeffs[2 * i + 1] = coeffs.1;
+        }
+        h
+    }
+
+    // Algorithm 11 with the 128 pairs in the order given by perm
+    pub fn multiply_ntt_shuffled(f: &Polynomial, g: &Polynomial, perm: &Permutation) -> Polynomial {
         let mut h = Polynomial::zero_polynomial();
-        for i in 0..128 {
+        for i in perm.iter() {
             let coeffs = base_case_multiply(f.coeffs[2 * i], f.coeffs[2 * i + 1], g.coeffs[2 * i], g.coeffs[2 * i + 1], FF::new(CONST2[i]));
             h.coeffs[2 * i] = coeffs.0;
             h.coeffs[2 * i + 1] = coeffs.1;
@@ -95,11 +187,7 @@ impl Add<Polynomial> for Polynomial {
     type Output = Polynomial;
 
     fn add(self, other: Polynomial) -> Polynomial {
-        let mut sum = Vec::new();
-        for i in 0..Polynomial::N {
-            sum.push(self.coeffs[i] + other.coeffs[i]);
-        }
-        Polynomial::new(sum)
+        Polynomial::new(core::array::from_fn(|i| self.coeffs[i] + other.coeffs[i]))
     }
 }
 
@@ -107,11 +195,7 @@ impl Sub<Polynomial> for Polynomial {
     type Output = Polynomial;
 
     fn sub(self, other: Polynomial) -> Polynomial {
-        let mut sum = Vec::new();
-        for i in 0..Polynomial::N {
-            sum.push(self.coeffs[i] - other.coeffs[i]);
-        }
-        Polynomial::new(sum)
+        Polynomial::new(core::array::from_fn(|i| self.coeffs[i] - other.coeffs[i]))
     }
 }
 
@@ -121,20 +205,27 @@ impl Mul<Polynomial> for Polynomial {
     fn mul(self, other: Polynomial) -> Polynomial {
         let f_ntt = self.ntt();
         let g_ntt = other.ntt();
-        let h_ntt = Polynomial::multiply_ntt(f_ntt, g_ntt);
+        let h_ntt = Polynomial::multiply_ntt(&f_ntt, &g_ntt);
         h_ntt.intt()
     }
 }
 
 //Algorithm 7: Takes a 32-byte seed and two indices as input and outputs a pseudorandom element of 𝑇𝑞.
-pub fn sample_ntt(mut bytes: Vec<u8>, i: u8, j:u8) -> Polynomial {
-    bytes.push(i);
-    bytes.push(j);
-    assert_eq!(bytes.len(), 34);
+pub fn sample_ntt(rho: &[u8], i: u8, j:u8) -> Result<Polynomial, Error> {
+    if rho.len() != 32 {
+        return Err(Error::InvalidLength);
+    }
+    let mut bytes = [0u8; 34];
+    bytes[..32].copy_from_slice(rho);
+    bytes[32] = i;
+    bytes[33] = j;
+    let mut ctx = Xof::new(&bytes);
     let mut a = Polynomial::zero_polynomial();
     let mut j = 0;
     while j < N {
-        let c = xof(bytes.clone());
+        let mut c = [0u8; 3];
+        ctx.squeeze(&mut c);
+        let c = c.map(|x| x as u16);
         let d1: u16 = c[0] + 256 * (c[1] % 16);
         let d2: u16 = c[1].div_euclid(16) + 16 * c[2];
         if d1 < Q {
@@ -146,49 +237,49 @@ pub fn sample_ntt(mut bytes: Vec<u8>, i: u8, j:u8) -> Polynomial {
             j += 1;
         }
     }
-    a
+    Ok(a)
 }
 
 // Algorithm 8: Takes a seed as input and outputs a pseudorandom sample from the distribution D𝜂(𝑅𝑞).
-pub fn sample_poly_cbd(mut bytes: Vec<u16>, eta: usize) -> Polynomial {
-    while bytes.len() < 64 * eta {
-        bytes.push(0);
+// The bits of the 64 eta input bytes are read in place, see bytes_to_bits.
+pub fn sample_poly_cbd(bytes: &[u8], eta: usize) -> Result<Polynomial, Error> {
+    if bytes.len() != 64 * eta {
+        return Err(Error::InvalidLength);
     }
-    let mut f = vec![FF(0); N];
-    let bits = bytes_to_bits(bytes);
+    let bit = |k: usize| ((bytes[k / 8] >> (k % 8)) & 1) as u16;
+    let mut f = Polynomial::zero_polynomial();
     for i in 0..N {
         let mut x = 0u16;
         let mut y = 0u16;
         for j in 0..eta {
-            x += bits[2 * i * eta + j];
-            y += bits[(2 * i + 1) * eta + j];
-            f[i] = FF(x) - FF(y);
+            x += bit(2 * i * eta + j);
+            y += bit((2 * i + 1) * eta + j);
         }
+        f.coeffs[i] = FF(x) - FF(y);
     }
-    Polynomial::new(f)
+    Ok(f)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::SeedableRng;
+    use std::format;
 
     #[test]
-    fn test_padding_zeros() {
-        let p = Polynomial::new(vec![FF(1), FF(2), FF(3)]);
-        let padded_p = Polynomial::padding_zeros(p.coeffs);
+    fn test_from_slice() {
+        let padded_p = Polynomial::from_slice(&[FF(1), FF(2), FF(3)]).coeffs;
         assert_eq!(padded_p.len(), N);
         assert_eq!(padded_p[0], FF(1));
         assert_eq!(padded_p[1], FF(2));
         assert_eq!(padded_p[2], FF(3));
-        for i in 3..N {
-            assert_eq!(padded_p[i], FF(0));
-        }
+        assert!(padded_p[3..].iter().all(|x| *x == FF(0)));
     }
 
     #[test]
     fn test_add() {
-        let p1 = Polynomial::new(vec![FF(1), FF(2), FF(3)]);
-        let p2 = Polynomial::new(vec![FF(4), FF(5), FF(6)]);
+        let p1 = Polynomial::from_slice(&[FF(1), FF(2), FF(3)]);
+        let p2 = Polynomial::from_slice(&[FF(4), FF(5), FF(6)]);
         let sum = p1 + p2;
         assert_eq!(sum.coeffs[0], FF(5));
         assert_eq!(sum.coeffs[1], FF(7));
@@ -197,8 +288,8 @@ mod tests {
 
     #[test]
     fn test_multiply() {
-        let p1 = Polynomial::new(vec![FF(1), FF(2), FF(3)]);
-        let p2 = Polynomial::new(vec![FF(4), FF(5), FF(6)]);
+        let p1 = Polynomial::from_slice(&[FF(1), FF(2), FF(3)]);
+        let p2 = Polynomial::from_slice(&[FF(4), FF(5), FF(6)]);
         let product = p1 * p2;
         assert_eq!(product.coeffs[0], FF(4));
         assert_eq!(product.coeffs[1], FF(13));
@@ -206,4 +297,48 @@ mod tests {
         assert_eq!(product.coeffs[3], FF(27));
         assert_eq!(product.coeffs[4], FF(18));
     }
+
+    #[test]
+    fn test_shuffled_matches_unshuffled() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(2);
+        let f = Polynomial::new(core::array::from_fn(|x| FF::new(x as u16 * 13)));
+        let g = Polynomial::new(core::array::from_fn(|x| FF::new(x as u16 * 7 + 1)));
+        for perm in [Permutation::identity(), Permutation::random(&mut rng).unwrap(), Permutation::random(&mut rng).unwrap()] {
+            assert_eq!(f.clone().ntt_shuffled(&perm), f.clone().ntt());
+            assert_eq!(f.clone().intt_shuffled(&perm), f.clone().intt());
+            assert_eq!(Polynomial::multiply_ntt_shuffled(&f, &g, &perm), Polynomial::multiply_ntt(&f, &g));
+        }
+    }
+
+    #[test]
+    fn test_debug_redacted() {
+        let p = Polynomial::from_slice(&[FF(1), FF(2)]);
+        assert_eq!(format!("{:?}", p), "Polynomial { .. }");
+        assert!(format!("{:?}", p.unredacted()).starts_with("Polynomial { coeffs: [1, 2, 0,"));
+    }
+
+    #[test]
+    fn test_sample_ntt_stream() {
+        let a = sample_ntt(&[0u8; 32], 0, 0).unwrap();
+        let b = sample_ntt(&[0u8; 32], 0, 1).unwrap();
+        assert_ne!(a, b);
+        assert!(a.coeffs.iter().any(|x| *x != a.coeffs[0]));
+        assert!(a.coeffs.iter().all(|x| x.0 < Q));
+    }
+
+    // Coefficients of SampleNTT(rho || 1 || 2) for rho = 0, 1, ..., 31, i.e. Â[2][1], computed
+    // independently from Algorithm 7 with Python's hashlib.shake_128. The keyGen vectors in tests/fixtures,
+    // computed with OpenSSL, also cover all of Â through t̂.
+    #[test]
+    fn test_sample_ntt_known_answer() {
+        let rho: [u8; 32] = core::array::from_fn(|i| i as u8);
+        let a = sample_ntt(&rho, 1, 2).unwrap().list();
+        assert_eq!(a[..16], [1642, 1316, 3309, 3204, 1436, 289, 1683, 2323, 2042, 193, 522, 1142, 963, 3263, 1619, 1548]);
+        assert_eq!(a[248..], [2915, 1324, 2335, 2483, 1181, 1973, 2847, 1454]);
+    }
+
+    #[test]
+    fn test_sample_ntt_seed_length() {
+        assert_eq!(sample_ntt(&[0u8; 31], 0, 0), Err(Error::InvalidLength));
+    }
 }
\ No newline at end of file
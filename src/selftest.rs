@@ -0,0 +1,191 @@
+use core::sync::atomic::{AtomicU8, Ordering};
+use crate::helper::{h, g, j, prf, Xof};
+use crate::mlkem::{keygen_internal, encaps_internal, decaps_internal};
+use crate::params::MlKem768;
+use crate::types::Ciphertext;
+use crate::error::Error;
+
+// Power-on self-tests: known-answer tests of the hash functions and of ML-KEM-768.
+// The public ML-KEM API refuses to operate until they have passed.
+const UNTESTED: u8 = 0;
+const PASSED: u8 = 1;
+const FAILED: u8 = 2;
+
+static STATE: AtomicU8 = AtomicU8::new(UNTESTED);
+
+const SHA3_256_ABC: [u8; 32] = [
+    0x3a, 0x98, 0x5d, 0xa7, 0x4f, 0xe2, 0x25, 0xb2, 0x04, 0x5c, 0x17, 0x2d, 0x6b, 0xd3, 0x90, 0xbd,
+    0x85, 0x5f, 0x08, 0x6e, 0x3e, 0x9d, 0x52, 0x5b, 0x46, 0xbf, 0xe2, 0x45, 0x11, 0x43, 0x15, 0x32,
+];
+
+const SHA3_512_ABC: [u8; 64] = [
+    0xb7, 0x51, 0x85, 0x0b, 0x1a, 0x57, 0x16, 0x8a, 0x56, 0x93, 0xcd, 0x92, 0x4b, 0x6b, 0x09, 0x6e,
+    0x08, 0xf6, 0x21, 0x82, 0x74, 0x44, 0xf7, 0x0d, 0x88, 0x4f, 0x5d, 0x02, 0x40, 0xd2, 0x71, 0x2e,
+    0x10, 0xe1, 0x16, 0xe9, 0x19, 0x2a, 0xf3, 0xc9, 0x1a, 0x7e, 0xc5, 0x76, 0x47, 0xe3, 0x93, 0x40,
+    0x57, 0x34, 0x0b, 0x4c, 0xf4, 0x08, 0xd5, 0xa5, 0x65, 0x92, 0xf8, 0x27, 0x4e, 0xec, 0x53, 0xf0,
+];
+
+const SHAKE128_ABC: [u8; 32] = [
+    0x58, 0x81, 0x09, 0x2d, 0xd8, 0x18, 0xbf, 0x5c, 0xf8, 0xa3, 0xdd, 0xb7, 0x93, 0xfb, 0xcb, 0xa7,
+    0x40, 0x97, 0xd5, 0xc5, 0x26, 0xa6, 0xd3, 0x5f, 0x97, 0xb8, 0x33, 0x51, 0x94, 0x0f, 0x2c, 0xc8,
+];
+
+const SHAKE256_ABC: [u8; 32] = [
+    0x48, 0x33, 0x66, 0x60, 0x13, 0x60, 0xa8, 0x77, 0x1c, 0x68, 0x63, 0x08, 0x0c, 0xc4, 0x11, 0x4d,
+    0x8d, 0xb4, 0x45, 0x30, 0xf8, 0xf1, 0xe1, 0xee, 0x4f, 0x94, 0xea, 0x37, 0xe7, 0x8b, 0x57, 0x39,
+];
+
+const H_EK: [u8; 32] = [
+    0xa2, 0x4e, 0x16, 0xd8, 0xf8, 0xf9, 0x38, 0x3a, 0x95, 0xb7, 0x70, 0x50, 0xf4, 0xd9, 0xfd, 0x2f,
+    0x57, 0x33, 0xee, 0xc1, 0xd6, 0x3e, 0xf3, 0xc2, 0x3e, 0xbf, 0x99, 0x18, 0x17, 0x36, 0x69, 0xa7,
+];
+
+const H_DK: [u8; 32] = [
+    0x11, 0x49, 0xf1, 0x7c, 0x3c, 0x4a, 0xc6, 0xab, 0x1e, 0x3e, 0x2d, 0x9d, 0x8b, 0xd0, 0x17, 0x13,
+    0x55, 0xac, 0x0f, 0xa3, 0x1b, 0xb8, 0x85, 0x5c, 0x48, 0xce, 0xad, 0xe8, 0x74, 0xc0, 0x86, 0x4b,
+];
+
+const H_C: [u8; 32] = [
+    0xb4, 0xcf, 0xbd, 0x24, 0xce, 0xf6, 0x7a, 0xfd, 0x37, 0x64, 0x27, 0x6c, 0x69, 0x80, 0xe0, 0xf8,
+    0x8f, 0x8e, 0x9c, 0xa5, 0x7f, 0x59, 0xb7, 0xf1, 0x2f, 0xe1, 0xa9, 0xc1, 0xe7, 0x2f, 0x47, 0x10,
+];
+
+const K: [u8; 32] = [
+    0x9c, 0xdd, 0xd0, 0x89, 0xff, 0xe7, 0x0e, 0x39, 0x96, 0xe7, 0x6f, 0x7c, 0x8d, 0x06, 0x74, 0x6d,
+    0xf3, 0x4d, 0x07, 0xe8, 0x65, 0x7b, 0xc0, 0xfc, 0xf2, 0xbb, 0x0e, 0x1c, 0x30, 0x84, 0xae, 0xa1,
+];
+
+const K_BAR: [u8; 32] = [
+    0xdc, 0xfc, 0x80, 0xc6, 0xdb, 0x46, 0xff, 0x70, 0x28, 0xe3, 0xa4, 0x39, 0x86, 0x51, 0xc0, 0x63,
+    0xae, 0x7a, 0x42, 0xc1, 0x07, 0xa6, 0xdc, 0x8c, 0xb0, 0x71, 0x41, 0x86, 0x16, 0x98, 0xab, 0x92,
+];
+
+fn check(ok: bool) -> Result<(), Error> {
+    if ok {
+        Ok(())
+    } else {
+        Err(Error::SelfTestFailed)
+    }
+}
+
+fn hash_self_tests() -> Result<(), Error> {
+    check(h(b"abc") == SHA3_256_ABC)?;
+    let (a, b) = g(b"abc");
+    check(a[..] == SHA3_512_ABC[..32] && b[..] == SHA3_512_ABC[32..])?;
+    let mut xof = [0u8; 32];
+    Xof::new(b"abc").squeeze(&mut xof);
+    check(xof == SHAKE128_ABC)?;
+    check(j(b"abc") == SHAKE256_ABC)?;
+    let mut prf_output = [0u8; 64 * 2];
+    prf(b"ab", b'c', &mut prf_output);
+    check(prf_output[..32] == SHAKE256_ABC)
+}
+
+fn mlkem_self_tests() -> Result<(), Error> {
+    let d: [u8; 32] = core::array::from_fn(|i| i as u8);
+    let z: [u8; 32] = core::array::from_fn(|i| 32 + i as u8);
+    let m: [u8; 32] = core::array::from_fn(|i| 64 + i as u8);
+
+    let (ek, dk) = keygen_internal::<MlKem768>(d, z)?;
+    check(h(ek.as_bytes()) == H_EK)?;
+    check(h(dk.as_bytes()) == H_DK)?;
+
+    let (k, c) = encaps_internal(&ek, m)?;
+    check(h(c.as_bytes()) == H_C)?;
+    check(k.as_bytes() == &K)?;
+    check(decaps_internal(&dk, &c)?.as_bytes() == &K)?;
+
+    // Implicit rejection
+    let mut c_bar = [0u8; 1088];
+    c_bar.copy_from_slice(c.as_bytes());
+    c_bar[0] ^= 1;
+    let c_bar = Ciphertext::<MlKem768>::from_bytes(&c_bar)?;
+    check(decaps_internal(&dk, &c_bar)?.as_bytes() == &K_BAR)
+}
+
+// Runs all self-tests and records the result; a known-answer mismatch is permanent
+pub fn self_test() -> Result<(), Error> {
+    if STATE.load(Ordering::Acquire) == FAILED {
+        return Err(Error::SelfTestFailed);
+    }
+    record(hash_self_tests().and_then(|_| mlkem_self_tests()))
+}
+
+// Only SelfTestFailed, from check, latches FAILED. Other errors, such as LockedMemory, say nothing
+// about the implementation, so they are returned as they are and the tests run again on next use.
+fn record(result: Result<(), Error>) -> Result<(), Error> {
+    match result {
+        Ok(()) => STATE.store(PASSED, Ordering::Release),
+        Err(Error::SelfTestFailed) => STATE.store(FAILED, Ordering::Release),
+        Err(_) => {}
+    }
+    result
+}
+
+// Runs the self-tests on first use and refuses to operate if they failed
+pub(crate) fn ensure_self_test() -> Result<(), Error> {
+    match STATE.load(Ordering::Acquire) {
+        PASSED => Ok(()),
+        FAILED => Err(Error::SelfTestFailed),
+        _ => self_test(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_self_test() {
+        assert_eq!(self_test(), Ok(()));
+        assert_eq!(ensure_self_test(), Ok(()));
+        assert_eq!(STATE.load(Ordering::Acquire), PASSED);
+    }
+
+    // The public API after a failed self-test; FAILED is permanent, so this runs in a child process
+    fn failed_self_test_disables_api() {
+        use crate::mlkem::*;
+        use crate::types::{DecapsulationKey, KeyPair};
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let (ek, dk) = keygen_internal::<MlKem768>([2; 32], [3; 32]).unwrap();
+        let (_, c) = encaps_internal(&ek, [4; 32]).unwrap();
+        let mut rng = StdRng::seed_from_u64(2);
+
+        // A resource error is passed through and leaves the state untouched
+        STATE.store(UNTESTED, Ordering::Release);
+        assert_eq!(record(Err(Error::LockedMemory)), Err(Error::LockedMemory));
+        assert_eq!(STATE.load(Ordering::Acquire), UNTESTED);
+        assert_eq!(ensure_self_test(), Ok(()));
+
+        let failed = Err(Error::SelfTestFailed);
+        assert_eq!(record(failed), failed);
+        assert_eq!(STATE.load(Ordering::Acquire), FAILED);
+        assert_eq!(self_test(), failed);
+        assert_eq!(keygen_with_rng::<MlKem768, _>(&mut rng).map(|_| ()), failed);
+        assert_eq!(KeyPair::<MlKem768>::from_seed([2; 32], [3; 32]).map(|_| ()), failed);
+        assert_eq!(DecapsulationKey::<MlKem768>::from_seed([2; 64]).map(|_| ()), failed);
+        assert_eq!(encaps_with_rng(&ek, &mut rng).map(|_| ()), failed);
+        assert_eq!(encapsulate_deterministic(&ek, [4; 32]).map(|_| ()), failed);
+        assert_eq!(encapsulate_into_with_rng(&ek, &mut [0; 1088], &mut [0; 32], &mut rng), failed);
+        assert_eq!(decaps(&dk, &c).map(|_| ()), failed);
+        assert_eq!(decapsulate_into(&dk, &c, &mut [0; 32]), failed);
+        assert_eq!(decaps_masked_with_rng(&dk, &c, &mut rng).map(|_| ()), failed);
+        assert_eq!(decaps_shuffled_with_rng(&dk, &c, &mut rng).map(|_| ()), failed);
+        assert_eq!(decaps_hardened(&dk, &c).map(|_| ()), failed);
+        assert_eq!(dk.expand().map(|_| ()), failed);
+    }
+
+    #[test]
+    fn test_failed_self_test_disables_api() {
+        if std::env::var_os("ML_KEM_SELF_TEST_CHILD").is_some() {
+            return failed_self_test_disables_api();
+        }
+        let status = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", "selftest::tests::test_failed_self_test_disables_api", "--test-threads=1"])
+            .env("ML_KEM_SELF_TEST_CHILD", "1")
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+}
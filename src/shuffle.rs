@@ -0,0 +1,83 @@
+use crate::error::Error;
+use rand::{CryptoRng, RngCore};
+use zeroize::Zeroize;
+use core::fmt;
+use crate::debug::DebugSecret;
+
+// Shuffling countermeasure: the 128 independent butterflies of an NTT layer, and the 128 pairs
+// of multiply_ntt, are processed in a random order, so that a power trace does not reveal
+// which coefficient is handled when. The results are the same as in the unshuffled order.
+
+// A permutation of 0..128
+#[derive(Clone, PartialEq, Eq)]
+pub struct Permutation {
+    order: [u8; 128],
+}
+
+impl Permutation {
+    pub fn identity() -> Permutation {
+        Permutation { order: core::array::from_fn(|i| i as u8) }
+    }
+
+    // Fisher-Yates shuffle, with rejection sampling so that every permutation is equally likely
+    pub fn random<R: RngCore + CryptoRng>(rng: &mut R) -> Result<Permutation, Error> {
+        let mut permutation = Permutation::identity();
+        for i in (1..128usize).rev() {
+            let mask = (i + 1).next_power_of_two() - 1;
+            let j = loop {
+                let mut byte = [0u8; 1];
+                rng.try_fill_bytes(&mut byte).map_err(|_| Error::Rng)?;
+                let j = byte[0] as usize & mask;
+                if j <= i {
+                    break j;
+                }
+            };
+            permutation.order.swap(i, j);
+        }
+        Ok(permutation)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.order.iter().map(|i| *i as usize)
+    }
+}
+
+impl fmt::Debug for Permutation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Permutation").finish_non_exhaustive()
+    }
+}
+
+impl DebugSecret for Permutation {
+    fn fmt_secret(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Permutation").field("order", &self.order).finish()
+    }
+}
+
+// The order itself says which coefficient is processed when
+impl Drop for Permutation {
+    fn drop(&mut self) {
+        self.order.zeroize();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::vec::Vec;
+
+    #[test]
+    fn test_random_permutation() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let a = Permutation::random(&mut rng).unwrap();
+        let b = Permutation::random(&mut rng).unwrap();
+        assert_ne!(a, b);
+        assert_ne!(a, Permutation::identity());
+
+        let mut sorted: Vec<usize> = a.iter().collect();
+        sorted.sort();
+        assert_eq!(sorted, Permutation::identity().iter().collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,499 @@
+use crate::params::{ByteArray, ParameterSet};
+use crate::error::Error;
+use crate::debug::DebugSecret;
+use crate::selftest::ensure_self_test;
+use crate::locked::LockedBox;
+use zeroize::{Zeroize, ZeroizeOnDrop};
+use core::fmt;
+use crate::mlkem::{keygen_internal, validate_ciphertext, validate_decapsulation_key};
+use crate::helper::ct_eq;
+use crate::kpke::{kpke_expand_dk, kpke_expand_ek};
+use crate::matrix::FixedArray;
+use crate::poly::Polynomial;
+
+// Encapsulation key ek, 384k + 32 bytes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncapsulationKey<P: ParameterSet> {
+    bytes: P::EncapsulationKeyBytes,
+}
+
+// Decapsulation key dk = dk_pke || ek || H(ek) || z, 768k + 96 bytes, with ŝ decoded once from dk_pke,
+// together with the 64-byte seed d || z when the key was generated from one.
+// All are kept in a LockedBox, i.e. in locked memory with the `mlock` feature.
+// Without it they are stored inline and moving the key leaves an unwiped copy behind.
+pub struct DecapsulationKey<P: ParameterSet> {
+    bytes: LockedBox<P::DecapsulationKeyBytes>,
+    s_hat: LockedBox<P::PolyVec>,
+    seed: Option<LockedBox<[u8; 64]>>,
+}
+
+// Decapsulation key with ŝ and t̂ decoded and Â sampled once, all in the NTT domain, together with
+// H(ek) and z, so repeated decapsulations skip ByteDecode and SampleNTT. ŝ and z are kept in a LockedBox.
+pub struct ExpandedDecapsulationKey<P: ParameterSet> {
+    s_hat: LockedBox<P::PolyVec>,
+    t_hat: P::PolyVec,
+    a_hat: P::PolyMatrix,
+    h: [u8; 32],
+    z: LockedBox<[u8; 32]>,
+}
+
+// Ciphertext c = c1 || c2, 32(du k + dv) bytes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ciphertext<P: ParameterSet> {
+    bytes: P::CiphertextBytes,
+}
+
+// Key pair (ek, dk) derived from the seeds d and z
+pub struct KeyPair<P: ParameterSet> {
+    ek: EncapsulationKey<P>,
+    dk: DecapsulationKey<P>,
+}
+
+// Shared secret key K, 32 bytes, wiped on drop. Moving it leaves an unwiped copy behind;
+// decapsulate_into writes K into a caller-owned buffer instead.
+#[derive(Clone)]
+pub struct SharedSecret {
+    bytes: [u8; 32],
+}
+
+// Secret-bearing types only print their type name and parameter set, see DebugSecret
+impl<P: ParameterSet> fmt::Debug for DecapsulationKey<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DecapsulationKey").field("parameter_set", &P::NAME).finish_non_exhaustive()
+    }
+}
+
+impl<P: ParameterSet> DebugSecret for DecapsulationKey<P> {
+    fn fmt_secret(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DecapsulationKey")
+            .field("parameter_set", &P::NAME)
+            .field("bytes", &*self.bytes)
+            .field("seed", &self.seed.as_deref())
+            .finish()
+    }
+}
+
+impl<P: ParameterSet> fmt::Debug for ExpandedDecapsulationKey<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExpandedDecapsulationKey").field("parameter_set", &P::NAME).finish_non_exhaustive()
+    }
+}
+
+impl<P: ParameterSet> DebugSecret for ExpandedDecapsulationKey<P> {
+    fn fmt_secret(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let a_hat = UnredactedRows(self.a_hat.as_ref());
+        f.debug_struct("ExpandedDecapsulationKey")
+            .field("parameter_set", &P::NAME)
+            .field("s_hat", &self.s_hat.as_ref().unredacted())
+            .field("t_hat", &self.t_hat.as_ref().unredacted())
+            .field("a_hat", &a_hat)
+            .field("h", &self.h)
+            .field("z", &*self.z)
+            .finish()
+    }
+}
+
+// The rows of a matrix such as Â, each printed unredacted
+struct UnredactedRows<'a, V>(&'a [V]);
+
+impl<V: AsRef<[Polynomial]>> fmt::Debug for UnredactedRows<'_, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.0.iter().map(|row| row.as_ref().unredacted())).finish()
+    }
+}
+
+impl<P: ParameterSet> fmt::Debug for KeyPair<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyPair").field("parameter_set", &P::NAME).finish_non_exhaustive()
+    }
+}
+
+impl<P: ParameterSet> DebugSecret for KeyPair<P> {
+    fn fmt_secret(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyPair")
+            .field("ek", &self.ek)
+            .field("dk", &self.dk.unredacted())
+            .finish()
+    }
+}
+
+impl fmt::Debug for SharedSecret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedSecret").finish_non_exhaustive()
+    }
+}
+
+impl DebugSecret for SharedSecret {
+    fn fmt_secret(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedSecret").field("bytes", &self.bytes).finish()
+    }
+}
+
+fn copy_from<A: ByteArray>(bytes: &[u8]) -> Result<A, Error> {
+    let mut array = A::zeroed();
+    if array.as_ref().len() != bytes.len() {
+        return Err(Error::InvalidLength);
+    }
+    array.as_mut().copy_from_slice(bytes);
+    Ok(array)
+}
+
+// Secrets are copied into storage that is allocated zeroed first, so they never pass through the stack
+fn locked_copy<A: ByteArray>(bytes: &[u8]) -> Result<LockedBox<A>, Error> {
+    let mut locked = LockedBox::new(A::zeroed())?;
+    if locked.as_ref().len() != bytes.len() {
+        return Err(Error::InvalidLength);
+    }
+    locked.as_mut().copy_from_slice(bytes);
+    Ok(locked)
+}
+
+fn locked_zero_vector<P: ParameterSet>() -> Result<LockedBox<P::PolyVec>, Error> {
+    LockedBox::new(P::PolyVec::from_fn(|_| Polynomial::zero_polynomial()))
+}
+
+fn locked_copy_vector<P: ParameterSet>(v: &P::PolyVec) -> Result<LockedBox<P::PolyVec>, Error> {
+    let mut locked = locked_zero_vector::<P>()?;
+    for (a, b) in locked.as_mut().iter_mut().zip(v.as_ref()) {
+        a.coeffs.copy_from_slice(&b.coeffs);
+    }
+    Ok(locked)
+}
+
+impl<P: ParameterSet> EncapsulationKey<P> {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        copy_from(bytes).map(|bytes| EncapsulationKey { bytes })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.bytes.as_ref()
+    }
+}
+
+impl<P: ParameterSet> DecapsulationKey<P> {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        validate_decapsulation_key::<P>(bytes)?;
+        // Copied and decoded straight into their final storage, leaving no copy of the key on the stack
+        let dk = locked_copy(bytes)?;
+        let mut s_hat = locked_zero_vector::<P>()?;
+        kpke_expand_dk::<P>(&bytes[..384 * P::K], &mut s_hat)?;
+        Ok(DecapsulationKey { bytes: dk, s_hat, seed: None })
+    }
+
+    // Expands the seed d || z through ML-KEM.KeyGen_internal
+    pub fn from_seed(mut seed: [u8; 64]) -> Result<Self, Error> {
+        let key_pair = KeyPair::<P>::from_seed_bytes(seed);
+        seed.zeroize();
+        Ok(key_pair?.into_parts().1)
+    }
+
+    // Imports both forms, rejecting them if the seed does not expand to bytes
+    pub fn from_seed_and_bytes(mut seed: [u8; 64], bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() != P::DK_LEN {
+            seed.zeroize();
+            return Err(Error::InvalidLength);
+        }
+        let dk = DecapsulationKey::from_seed(seed);
+        seed.zeroize();
+        let dk = dk?;
+        // Compared in constant time, as both are secret
+        if ct_eq(dk.as_bytes(), bytes) != 0xff {
+            return Err(Error::KeyMismatch);
+        }
+        Ok(dk)
+    }
+
+    pub(crate) fn with_seed(mut self, seed: &[u8; 64]) -> Result<Self, Error> {
+        self.seed = Some(locked_copy(seed)?);
+        Ok(self)
+    }
+
+    // Fails with LockedMemory instead of panicking when no more memory can be locked
+    pub fn try_clone(&self) -> Result<Self, Error> {
+        let dk = DecapsulationKey { bytes: locked_copy(self.as_bytes())?, s_hat: locked_copy_vector::<P>(&self.s_hat)?, seed: None };
+        match self.seed() {
+            Some(seed) => dk.with_seed(seed),
+            None => Ok(dk),
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.bytes.as_ref()
+    }
+
+    pub fn seed(&self) -> Option<&[u8; 64]> {
+        self.seed.as_deref()
+    }
+
+    pub(crate) fn s_hat(&self) -> &P::PolyVec {
+        &self.s_hat
+    }
+
+    pub fn expand(&self) -> Result<ExpandedDecapsulationKey<P>, Error> {
+        ensure_self_test()?;
+        let k = P::K;
+        let dk = self.as_bytes();
+        let s_hat = locked_copy_vector::<P>(&self.s_hat)?;
+        let (t_hat, a_hat) = kpke_expand_ek::<P>(&dk[384*k..768*k+32])?;
+        let mut h = [0u8; 32];
+        h.copy_from_slice(&dk[768*k+32..768*k+64]);
+        let z = locked_copy(&dk[768*k+64..])?;
+        Ok(ExpandedDecapsulationKey { s_hat, t_hat, a_hat, h, z })
+    }
+}
+
+impl<P: ParameterSet> ExpandedDecapsulationKey<P> {
+    // Fails with LockedMemory instead of panicking when no more memory can be locked
+    pub fn try_clone(&self) -> Result<Self, Error> {
+        Ok(ExpandedDecapsulationKey {
+            s_hat: locked_copy_vector::<P>(&self.s_hat)?,
+            t_hat: self.t_hat.clone(),
+            a_hat: self.a_hat.clone(),
+            h: self.h,
+            z: locked_copy(&*self.z)?,
+        })
+    }
+
+    pub(crate) fn s_hat(&self) -> &P::PolyVec {
+        &self.s_hat
+    }
+
+    pub(crate) fn t_hat(&self) -> &P::PolyVec {
+        &self.t_hat
+    }
+
+    pub(crate) fn a_hat(&self) -> &P::PolyMatrix {
+        &self.a_hat
+    }
+
+    pub(crate) fn h(&self) -> &[u8; 32] {
+        &self.h
+    }
+
+    pub(crate) fn z(&self) -> &[u8; 32] {
+        &self.z
+    }
+}
+
+// ŝ and z are wiped by LockedBox when dropped
+impl<P: ParameterSet> ZeroizeOnDrop for ExpandedDecapsulationKey<P> {}
+
+// Compared in constant time, as dk is secret
+impl<P: ParameterSet> PartialEq for DecapsulationKey<P> {
+    fn eq(&self, other: &Self) -> bool {
+        ct_eq(self.as_bytes(), other.as_bytes()) == 0xff
+    }
+}
+
+impl<P: ParameterSet> Eq for DecapsulationKey<P> {}
+
+// Both halves are always compared, dk in constant time
+impl<P: ParameterSet> PartialEq for KeyPair<P> {
+    fn eq(&self, other: &Self) -> bool {
+        let ek = ct_eq(self.ek.as_bytes(), other.ek.as_bytes());
+        let dk = ct_eq(self.dk.as_bytes(), other.dk.as_bytes());
+        ek & dk == 0xff
+    }
+}
+
+impl<P: ParameterSet> Eq for KeyPair<P> {}
+
+// dk, ŝ and the seed are wiped by LockedBox when dropped
+impl<P: ParameterSet> ZeroizeOnDrop for DecapsulationKey<P> {}
+
+impl<P: ParameterSet> Ciphertext<P> {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        validate_ciphertext::<P>(bytes)?;
+        copy_from(bytes).map(|bytes| Ciphertext { bytes })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.bytes.as_ref()
+    }
+}
+
+impl<P: ParameterSet> KeyPair<P> {
+    // Deterministic key generation from the seeds d and z (ML-KEM.KeyGen_internal), wiped before returning
+    pub fn from_seed(mut d: [u8; 32], mut z: [u8; 32]) -> Result<Self, Error> {
+        let keys = ensure_self_test().and_then(|_| keygen_internal::<P>(d, z));
+        d.zeroize();
+        z.zeroize();
+        let (ek, dk) = keys?;
+        Ok(KeyPair { ek, dk })
+    }
+
+    pub fn from_seed_bytes(mut seed: [u8; 64]) -> Result<Self, Error> {
+        let mut d = [0u8; 32];
+        let mut z = [0u8; 32];
+        d.copy_from_slice(&seed[..32]);
+        z.copy_from_slice(&seed[32..]);
+        let key_pair = KeyPair::from_seed(d, z);
+        seed.zeroize();
+        d.zeroize();
+        z.zeroize();
+        key_pair
+    }
+
+    pub fn encapsulation_key(&self) -> &EncapsulationKey<P> {
+        &self.ek
+    }
+
+    pub fn decapsulation_key(&self) -> &DecapsulationKey<P> {
+        &self.dk
+    }
+
+    pub fn into_parts(self) -> (EncapsulationKey<P>, DecapsulationKey<P>) {
+        (self.ek, self.dk)
+    }
+
+    pub fn try_clone(&self) -> Result<Self, Error> {
+        Ok(KeyPair { ek: self.ek.clone(), dk: self.dk.try_clone()? })
+    }
+}
+
+impl SharedSecret {
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        SharedSecret { bytes }
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.bytes
+    }
+}
+
+impl Drop for SharedSecret {
+    fn drop(&mut self) {
+        self.bytes.zeroize();
+    }
+}
+
+impl ZeroizeOnDrop for SharedSecret {}
+
+// Secret values are compared in constant time
+impl PartialEq for SharedSecret {
+    fn eq(&self, other: &Self) -> bool {
+        ct_eq(&self.bytes, &other.bytes) == 0xff
+    }
+}
+
+impl Eq for SharedSecret {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::params::MlKem768;
+    use std::format;
+    use std::vec::Vec;
+    use core::mem::ManuallyDrop;
+
+    #[test]
+    fn test_from_bytes_length() {
+        assert!(EncapsulationKey::<MlKem768>::from_bytes(&[0u8; 1184]).is_ok());
+        assert!(EncapsulationKey::<MlKem768>::from_bytes(&[0u8; 1183]).is_err());
+        assert_eq!(DecapsulationKey::<MlKem768>::from_bytes(&[0u8; 2400]), Err(Error::HashCheck));
+        assert!(DecapsulationKey::<MlKem768>::from_bytes(&[0u8; 1184]).is_err());
+        assert!(Ciphertext::<MlKem768>::from_bytes(&[0u8; 1088]).is_ok());
+        assert!(Ciphertext::<MlKem768>::from_bytes(&[0u8; 1568]).is_err());
+    }
+
+    #[test]
+    fn test_as_bytes() {
+        let bytes: Vec<u8> = (0..1088).map(|x| x as u8).collect();
+        let c = Ciphertext::<MlKem768>::from_bytes(&bytes).unwrap();
+        assert_eq!(c.as_bytes(), &bytes[..]);
+    }
+
+    #[test]
+    fn test_debug_redacted() {
+        let key_pair = KeyPair::<MlKem768>::from_seed([2; 32], [3; 32]).unwrap();
+        assert_eq!(format!("{:?}", key_pair), "KeyPair { parameter_set: \"ML-KEM-768\", .. }");
+        assert_eq!(format!("{:?}", key_pair.decapsulation_key()), "DecapsulationKey { parameter_set: \"ML-KEM-768\", .. }");
+        assert_eq!(format!("{:?}", SharedSecret::from_bytes([7; 32])), "SharedSecret { .. }");
+        let expanded = key_pair.decapsulation_key().expand().unwrap();
+        assert_eq!(format!("{:?}", expanded), "ExpandedDecapsulationKey { parameter_set: \"ML-KEM-768\", .. }");
+
+        let dump = format!("{:?}", key_pair.decapsulation_key().unredacted());
+        assert!(dump.contains("seed: Some([2, 2,"));
+        let dump = format!("{:?}", expanded.unredacted());
+        assert!(dump.starts_with("ExpandedDecapsulationKey { parameter_set: \"ML-KEM-768\", s_hat: [Polynomial { coeffs: ["));
+        assert!(dump.contains("z: [3, 3,"));
+        assert!(format!("{:?}", SharedSecret::from_bytes([7; 32]).unredacted()).contains("[7, 7,"));
+    }
+
+    // The destructors run in place inside a ManuallyDrop, whose storage can still be read afterwards
+    #[test]
+    fn test_shared_secret_wiped_on_drop() {
+        let mut k = ManuallyDrop::new(SharedSecret::from_bytes([7; 32]));
+        let ptr = k.as_bytes().as_ptr();
+        unsafe { ManuallyDrop::drop(&mut k) };
+        assert_eq!(unsafe { core::slice::from_raw_parts(ptr, 32) }, [0; 32]);
+    }
+
+    // With `mlock` the storage is unmapped after the wipe and cannot be read back
+    #[cfg(not(all(feature = "mlock", target_os = "linux")))]
+    #[test]
+    fn test_decapsulation_key_wiped_on_drop() {
+        let dk = KeyPair::<MlKem768>::from_seed([2; 32], [3; 32]).unwrap().into_parts().1;
+        let mut dk = ManuallyDrop::new(dk);
+        let (ptr, seed_ptr) = (dk.as_bytes().as_ptr(), dk.seed().unwrap().as_ptr());
+        assert!(dk.as_bytes().iter().any(|b| *b != 0));
+        unsafe { ManuallyDrop::drop(&mut dk) };
+        let (bytes, seed) = unsafe { (core::slice::from_raw_parts(ptr, MlKem768::DK_LEN), core::slice::from_raw_parts(seed_ptr, 64)) };
+        assert!(bytes.iter().chain(seed).all(|b| *b == 0));
+    }
+
+    #[test]
+    fn test_key_pair_from_seed() {
+        let key_pair = KeyPair::<MlKem768>::from_seed([2; 32], [3; 32]).unwrap();
+        let (ek, dk) = keygen_internal::<MlKem768>([2; 32], [3; 32]).unwrap();
+        assert_eq!(key_pair.encapsulation_key(), &ek);
+        assert_eq!(key_pair.decapsulation_key(), &dk);
+        assert_eq!(&dk.as_bytes()[2368..], &[3; 32]);
+    }
+
+    #[test]
+    fn test_eq() {
+        let a = KeyPair::<MlKem768>::from_seed([2; 32], [3; 32]).unwrap();
+        let b = KeyPair::<MlKem768>::from_seed([2; 32], [4; 32]).unwrap();
+        assert_eq!(a, KeyPair::<MlKem768>::from_seed([2; 32], [3; 32]).unwrap());
+        assert_ne!(a, b);
+        assert_ne!(a.decapsulation_key(), b.decapsulation_key());
+        assert_eq!(SharedSecret::from_bytes([7; 32]), SharedSecret::from_bytes([7; 32]));
+        assert_ne!(SharedSecret::from_bytes([7; 32]), SharedSecret::from_bytes([8; 32]));
+    }
+
+    #[test]
+    fn test_try_clone() {
+        let key_pair = KeyPair::<MlKem768>::from_seed([2; 32], [3; 32]).unwrap();
+        let copy = key_pair.try_clone().unwrap();
+        assert_eq!(copy, key_pair);
+        assert_eq!(copy.decapsulation_key().seed(), key_pair.decapsulation_key().seed());
+
+        let (k, c) = crate::mlkem::encapsulate_deterministic(key_pair.encapsulation_key(), [4; 32]).unwrap();
+        let dk = DecapsulationKey::<MlKem768>::from_bytes(key_pair.decapsulation_key().as_bytes()).unwrap().try_clone().unwrap();
+        assert_eq!(dk.seed(), None);
+        assert_eq!(crate::mlkem::decaps(&dk, &c).unwrap(), k);
+        let expanded = dk.expand().unwrap().try_clone().unwrap();
+        assert_eq!(crate::mlkem::decaps_expanded(&expanded, &c).unwrap(), k);
+    }
+
+    #[test]
+    fn test_decapsulation_key_seed() {
+        let mut seed = [2u8; 64];
+        seed[32..].copy_from_slice(&[3; 32]);
+        let dk = DecapsulationKey::<MlKem768>::from_seed(seed).unwrap();
+        assert_eq!(dk.seed(), Some(&seed));
+        assert_eq!(&dk, KeyPair::<MlKem768>::from_seed([2; 32], [3; 32]).unwrap().decapsulation_key());
+
+        let imported = DecapsulationKey::<MlKem768>::from_bytes(dk.as_bytes()).unwrap();
+        assert_eq!(imported.seed(), None);
+        assert_eq!(imported, dk);
+
+        let both = DecapsulationKey::<MlKem768>::from_seed_and_bytes(seed, dk.as_bytes()).unwrap();
+        assert_eq!(both.seed(), Some(&seed));
+
+        assert_eq!(DecapsulationKey::<MlKem768>::from_seed_and_bytes(seed, &dk.as_bytes()[1..]), Err(Error::InvalidLength));
+        seed[0] ^= 1;
+        assert_eq!(DecapsulationKey::<MlKem768>::from_seed_and_bytes(seed, dk.as_bytes()), Err(Error::KeyMismatch));
+    }
+}
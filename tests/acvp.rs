@@ -0,0 +1,113 @@
+// Known-answer tests against ACVP ML-KEM vectors (internalProjection.json format).
+// The checked-in vectors under tests/fixtures were computed with OpenSSL, see tests/fixtures/README.md.
+// Set ACVP_VECTORS_DIR to a directory holding the official ACVP-Server ML-KEM-keyGen-FIPS203 and
+// ML-KEM-encapDecap-FIPS203 folders to run every official vector instead.
+use ml_kem::error::Error;
+// KeyPair::from_seed, encapsulate_deterministic and decaps are ML-KEM.KeyGen_internal, Encaps_internal and
+// Decaps_internal behind the self-test gate
+use ml_kem::mlkem::{encapsulate_deterministic, decaps, validate_encapsulation_key, validate_decapsulation_key};
+use ml_kem::params::{ParameterSet, MlKem512, MlKem768, MlKem1024};
+use ml_kem::types::{EncapsulationKey, DecapsulationKey, Ciphertext, KeyPair};
+use serde_json::Value;
+
+fn load(name: &str, mode: &str) -> Value {
+    let dir = std::env::var("ACVP_VECTORS_DIR").unwrap_or_else(|_| format!("{}/tests/fixtures", env!("CARGO_MANIFEST_DIR")));
+    let path = format!("{}/{}/internalProjection.json", dir, name);
+    let data = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path, e));
+    let vectors: Value = serde_json::from_str(&data).unwrap();
+    assert_eq!(vectors["algorithm"], "ML-KEM", "{}", path);
+    assert_eq!(vectors["mode"], mode, "{}", path);
+    assert_eq!(vectors["revision"], "FIPS203", "{}", path);
+    vectors
+}
+
+fn hex(value: &Value) -> Vec<u8> {
+    let s = value.as_str().unwrap();
+    (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+}
+
+fn array(value: &Value) -> [u8; 32] {
+    hex(value).try_into().unwrap()
+}
+
+fn key_gen<P: ParameterSet>(test: &Value) {
+    let (ek, dk) = KeyPair::<P>::from_seed(array(&test["d"]), array(&test["z"])).unwrap().into_parts();
+    assert_eq!(ek.as_bytes(), &hex(&test["ek"])[..], "tcId {}", test["tcId"]);
+    assert_eq!(dk.as_bytes(), &hex(&test["dk"])[..], "tcId {}", test["tcId"]);
+}
+
+fn encapsulation<P: ParameterSet>(test: &Value) {
+    let ek = EncapsulationKey::<P>::from_bytes(&hex(&test["ek"])).unwrap();
+    let dk = DecapsulationKey::<P>::from_bytes(&hex(&test["dk"])).unwrap();
+    let (k, c) = encapsulate_deterministic(&ek, array(&test["m"])).unwrap();
+    assert_eq!(c.as_bytes(), &hex(&test["c"])[..], "tcId {}", test["tcId"]);
+    assert_eq!(k.as_bytes(), &array(&test["k"]), "tcId {}", test["tcId"]);
+    assert_eq!(decaps(&dk, &c).unwrap(), k, "tcId {}", test["tcId"]);
+}
+
+fn decapsulation<P: ParameterSet>(group: &Value, test: &Value) {
+    // dk is given per test case in newer ACVP files and per group in older ones
+    let dk = if test["dk"].is_null() { &group["dk"] } else { &test["dk"] };
+    let dk = DecapsulationKey::<P>::from_bytes(&hex(dk)).unwrap();
+    let c = Ciphertext::<P>::from_bytes(&hex(&test["c"])).unwrap();
+    let k = decaps(&dk, &c).unwrap();
+    assert_eq!(k.as_bytes(), &array(&test["k"]), "tcId {} ({})", test["tcId"], test["reason"]);
+}
+
+fn encapsulation_key_check<P: ParameterSet>(test: &Value) {
+    let result = validate_encapsulation_key::<P>(&hex(&test["ek"]));
+    let expected = test["testPassed"].as_bool().unwrap();
+    assert_eq!(result.is_ok(), expected, "tcId {} ({})", test["tcId"], test["reason"]);
+    if !expected {
+        assert_eq!(result, Err(Error::ModulusCheck));
+    }
+}
+
+fn decapsulation_key_check<P: ParameterSet>(test: &Value) {
+    let result = validate_decapsulation_key::<P>(&hex(&test["dk"]));
+    let expected = test["testPassed"].as_bool().unwrap();
+    assert_eq!(result.is_ok(), expected, "tcId {} ({})", test["tcId"], test["reason"]);
+    if !expected {
+        assert_eq!(result, Err(Error::HashCheck));
+    }
+}
+
+fn run_group<P: ParameterSet>(group: &Value) -> usize {
+    let tests = group["tests"].as_array().unwrap();
+    for test in tests {
+        match group["function"].as_str() {
+            None => key_gen::<P>(test),
+            Some("encapsulation") => encapsulation::<P>(test),
+            Some("decapsulation") => decapsulation::<P>(group, test),
+            Some("encapsulationKeyCheck") => encapsulation_key_check::<P>(test),
+            Some("decapsulationKeyCheck") => decapsulation_key_check::<P>(test),
+            Some(function) => panic!("unknown function {}", function),
+        }
+    }
+    tests.len()
+}
+
+// Runs every test case of every group and returns how many ran
+fn run(name: &str, mode: &str) -> usize {
+    let vectors = load(name, mode);
+    let mut count = 0;
+    for group in vectors["testGroups"].as_array().unwrap() {
+        count += match group["parameterSet"].as_str().unwrap() {
+            "ML-KEM-512" => run_group::<MlKem512>(group),
+            "ML-KEM-768" => run_group::<MlKem768>(group),
+            "ML-KEM-1024" => run_group::<MlKem1024>(group),
+            parameter_set => panic!("unknown parameter set {}", parameter_set),
+        };
+    }
+    count
+}
+
+#[test]
+fn test_acvp_key_gen() {
+    assert!(run("ML-KEM-keyGen-FIPS203", "keyGen") > 0);
+}
+
+#[test]
+fn test_acvp_encap_decap() {
+    assert!(run("ML-KEM-encapDecap-FIPS203", "encapDecap") > 0);
+}
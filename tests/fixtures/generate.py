@@ -0,0 +1,185 @@
+#!/usr/bin/env python3
+# Regenerates the fixtures in this directory with the OpenSSL 3.5+ ML-KEM implementation, so that
+# tests/acvp.rs checks this crate against an independent implementation. Needs `openssl` on PATH.
+#
+#     python3 tests/fixtures/generate.py
+import json
+import os
+import subprocess
+import tempfile
+
+PARAMETER_SETS = {
+    "ML-KEM-512": (2, 1),
+    "ML-KEM-768": (3, 2),
+    "ML-KEM-1024": (4, 3),
+}
+HERE = os.path.dirname(os.path.abspath(__file__))
+
+
+def der(tag, body):
+    n = len(body)
+    if n < 0x80:
+        length = bytes([n])
+    else:
+        raw = n.to_bytes((n.bit_length() + 7) // 8, "big")
+        length = bytes([0x80 | len(raw)]) + raw
+    return bytes([tag]) + length + body
+
+
+def algorithm(oid_last):
+    return der(0x30, der(0x06, bytes.fromhex("608648016503040401")[:-1] + bytes([oid_last])))
+
+
+# PKCS#8 with the expandedKey form of the private key, and SubjectPublicKeyInfo
+def private_der(oid_last, dk):
+    return der(0x30, der(0x02, b"\x00") + algorithm(oid_last) + der(0x04, der(0x04, dk)))
+
+
+def public_der(oid_last, ek):
+    return der(0x30, algorithm(oid_last) + der(0x03, b"\x00" + ek))
+
+
+class OpenSSL:
+    def __init__(self, tmp):
+        self.tmp = tmp
+
+    def path(self, name):
+        return os.path.join(self.tmp, name)
+
+    def run(self, *args, check=True):
+        result = subprocess.run(["openssl", *args], capture_output=True)
+        if check and result.returncode != 0:
+            raise SystemExit(result.stderr.decode())
+        return result
+
+    def write(self, name, data):
+        with open(self.path(name), "wb") as f:
+            f.write(data)
+        return self.path(name)
+
+    def read(self, name):
+        with open(self.path(name), "rb") as f:
+            return f.read()
+
+    # ML-KEM.KeyGen_internal(d, z) through the seed d || z
+    def key_gen(self, name, d, z):
+        self.run("genpkey", "-algorithm", name, "-pkeyopt", "hexseed:" + (d + z).hex(), "-outform", "DER", "-out", self.path("seed.der"))
+        text = self.run("pkey", "-inform", "DER", "-in", self.path("seed.der"), "-text", "-noout").stdout.decode()
+        fields, current = {}, None
+        for line in text.splitlines():
+            if not line.startswith(" ") and line.endswith(":"):
+                current = line[:-1]
+                fields[current] = ""
+            elif current is not None:
+                fields[current] += line.strip().replace(":", "")
+        return bytes.fromhex(fields["ek"]), bytes.fromhex(fields["dk"])
+
+    # ML-KEM.Encaps_internal(ek, m)
+    def encaps(self, oid_last, ek, m):
+        self.write("ek.der", public_der(oid_last, ek))
+        self.run("pkeyutl", "-encap", "-pubin", "-keyform", "DER", "-inkey", self.path("ek.der"),
+                 "-pkeyopt", "hexikme:" + m.hex(), "-out", self.path("c"), "-secret", self.path("k"))
+        return self.read("c"), self.read("k")
+
+    def decaps(self, oid_last, dk, c):
+        self.write("dk.der", private_der(oid_last, dk))
+        self.write("c", c)
+        self.run("pkeyutl", "-decap", "-keyform", "DER", "-inkey", self.path("dk.der"), "-in", self.path("c"), "-secret", self.path("k"))
+        return self.read("k")
+
+    def accepts_ek(self, oid_last, ek):
+        self.write("ek.der", public_der(oid_last, ek))
+        return self.run("pkey", "-pubin", "-inform", "DER", "-in", self.path("ek.der"), "-noout", check=False).returncode == 0
+
+    def accepts_dk(self, oid_last, dk):
+        self.write("dk.der", private_der(oid_last, dk))
+        return self.run("pkey", "-inform", "DER", "-in", self.path("dk.der"), "-noout", check=False).returncode == 0
+
+
+# Sets the first 12-bit coefficient of t̂ to q
+def modulus_overflow(ek):
+    ek = bytearray(ek)
+    ek[0] = 0x01
+    ek[1] = (ek[1] & 0xf0) | 0x0d
+    return bytes(ek)
+
+
+def header(mode):
+    return {"vsId": 0, "algorithm": "ML-KEM", "mode": mode, "revision": "FIPS203", "isSample": True}
+
+
+def main():
+    with tempfile.TemporaryDirectory() as tmp:
+        ossl = OpenSSL(tmp)
+        tc_id = 0
+
+        def test(**fields):
+            nonlocal tc_id
+            tc_id += 1
+            return {"tcId": tc_id, "deferred": False, **fields}
+
+        key_gen = header("keyGen")
+        key_gen["testGroups"] = []
+        for tg_id, name in enumerate(PARAMETER_SETS, 1):
+            tests = []
+            for _ in range(5):
+                d, z = os.urandom(32), os.urandom(32)
+                ek, dk = ossl.key_gen(name, d, z)
+                tests.append(test(z=z.hex().upper(), d=d.hex().upper(), ek=ek.hex().upper(), dk=dk.hex().upper()))
+            key_gen["testGroups"].append({"tgId": tg_id, "testType": "AFT", "parameterSet": name, "tests": tests})
+
+        tc_id = 0
+        groups = []
+
+        def group(test_type, name, function, tests, **fields):
+            groups.append({"tgId": len(groups) + 1, "testType": test_type, "parameterSet": name, "function": function, **fields, "tests": tests})
+
+        for name, (_, oid_last) in PARAMETER_SETS.items():
+            tests = []
+            for _ in range(5):
+                ek, dk = ossl.key_gen(name, os.urandom(32), os.urandom(32))
+                m = os.urandom(32)
+                c, k = ossl.encaps(oid_last, ek, m)
+                tests.append(test(ek=ek.hex().upper(), dk=dk.hex().upper(), c=c.hex().upper(), k=k.hex().upper(), m=m.hex().upper()))
+            group("AFT", name, "encapsulation", tests)
+        for name, (_, oid_last) in PARAMETER_SETS.items():
+            ek, dk = ossl.key_gen(name, os.urandom(32), os.urandom(32))
+            tests = []
+            for i in range(6):
+                c, _ = ossl.encaps(oid_last, ek, os.urandom(32))
+                reason = "valid decapsulation"
+                if i >= 3:
+                    # Implicit rejection: any change to c gives K̄ = J(z || c)
+                    c = bytearray(c)
+                    c[int.from_bytes(os.urandom(2), "big") % len(c)] ^= 1 << (i - 3)
+                    c = bytes(c)
+                    reason = "modified ciphertext"
+                tests.append(test(c=c.hex().upper(), k=ossl.decaps(oid_last, dk, c).hex().upper(), reason=reason))
+            group("VAL", name, "decapsulation", tests, ek=ek.hex().upper(), dk=dk.hex().upper())
+        for name, (_, oid_last) in PARAMETER_SETS.items():
+            ek, _ = ossl.key_gen(name, os.urandom(32), os.urandom(32))
+            tests = []
+            for ek_, reason in [(ek, "no modification"), (modulus_overflow(ek), "modulus overflow")]:
+                tests.append(test(ek=ek_.hex().upper(), testPassed=ossl.accepts_ek(oid_last, ek_), reason=reason))
+            group("VAL", name, "encapsulationKeyCheck", tests)
+        for name, (k, oid_last) in PARAMETER_SETS.items():
+            _, dk = ossl.key_gen(name, os.urandom(32), os.urandom(32))
+            modified = bytearray(dk)
+            modified[768 * k + 32] ^= 1
+            tests = []
+            for dk_, reason in [(dk, "no modification"), (bytes(modified), "modified H")]:
+                tests.append(test(dk=dk_.hex().upper(), testPassed=ossl.accepts_dk(oid_last, dk_), reason=reason))
+            group("VAL", name, "decapsulationKeyCheck", tests)
+
+        encap_decap = header("encapDecap")
+        encap_decap["testGroups"] = groups
+
+    for mode, vectors in [("keyGen", key_gen), ("encapDecap", encap_decap)]:
+        path = os.path.join(HERE, "ML-KEM-%s-FIPS203" % mode, "internalProjection.json")
+        with open(path, "w") as f:
+            json.dump(vectors, f, indent=2)
+            f.write("\n")
+
+
+if __name__ == "__main__":
+    main()